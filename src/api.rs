@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+use warp::http::Response;
+use warp::hyper::Body;
+use futures::stream;
+use std::io::{Cursor, Write};
+use tokio::task;
+use zip::{CompressionMethod, ZipWriter};
+use zip::write::FileOptions;
+use crate::error::Error;
+use crate::model::game::{GameFilter, GameRecord, RatingEntry};
+use crate::storage::Storage;
+use crate::tak::player::Player;
+use crate::tak::ptn;
+
+//read only json endpoints under /api for scripts and the website, nothing here needs a websocket
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 200;
+//zip archives are built in memory, larger exports have to be narrowed down or streamed as plain ptn
+const MAX_ZIP_GAMES: usize = 10_000;
+const PTN_CONTENT_TYPE: &str = "application/x-ptn; charset=utf-8";
+
+#[derive(Debug, Default, Deserialize)]
+struct GamesQuery {
+    player: Option<String>,
+    size: Option<u8>,
+    rated: Option<bool>,
+    before: Option<i64>,
+    after: Option<i64>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ExportFormat {
+    Ptn,
+    Zip,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    player: Option<String>,
+    before: Option<i64>,
+    after: Option<i64>,
+    format: Option<ExportFormat>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PageQuery {
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GameSummary {
+    id: Uuid,
+    date: i64,
+    size: u8,
+    white: String,
+    black: String,
+    result: String,
+    rated: bool,
+    white_rating: f64,
+    black_rating: f64,
+    time: u32,
+    increment: u32,
+    komi: f64,
+    stones: u8,
+    caps: u8,
+    no_swap: bool,
+}
+
+impl GameSummary {
+    fn new(game: &GameRecord) -> Self {
+        GameSummary {
+            id: game.id,
+            date: game.date,
+            size: game.size,
+            white: game.white.clone(),
+            black: game.black.clone(),
+            result: game.result.clone(),
+            rated: game.rated,
+            white_rating: game.white_rating,
+            black_rating: game.black_rating,
+            time: game.time,
+            increment: game.increment,
+            komi: game.komi,
+            stones: game.stones,
+            caps: game.caps,
+            no_swap: game.no_swap,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GamesPage {
+    games: Vec<GameSummary>,
+    limit: u32,
+    offset: u32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GameDetail {
+    #[serde(flatten)]
+    summary: GameSummary,
+    moves: Vec<String>,
+    ptn: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RatingPoint {
+    game_id: Option<Uuid>,
+    rating: f64,
+    time: i64,
+}
+
+impl RatingPoint {
+    fn new(entry: &RatingEntry) -> Self {
+        RatingPoint {
+            game_id: entry.game_id,
+            rating: entry.rating,
+            time: entry.time,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayerProfile {
+    name: String,
+    rating: f64,
+    games_played: u32,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+    joined: i64,
+    last_seen: i64,
+    rating_history: Vec<RatingPoint>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LeaderboardEntry {
+    rank: u32,
+    name: String,
+    rating: f64,
+    games_played: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiError {
+    error: &'static str,
+}
+
+fn page(limit: Option<u32>, offset: Option<u32>) -> (u32, u32) {
+    (limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE), offset.unwrap_or(0))
+}
+
+fn json_error(error: &'static str, status: StatusCode) -> Box<dyn Reply> {
+    Box::new(warp::reply::with_status(warp::reply::json(&ApiError { error }), status))
+}
+
+//storage failures are logged and answered with a bare 500, the details stay in the logs
+fn respond(result: Result<Box<dyn Reply>, Error>) -> Result<Box<dyn Reply>, Rejection> {
+    Ok(result.unwrap_or_else(|err| {
+        error!(error = %err, "Api request failed");
+        json_error("server-error", StatusCode::INTERNAL_SERVER_ERROR)
+    }))
+}
+
+pub fn routes(storage: Arc<dyn Storage>) -> BoxedFilter<(Box<dyn Reply>,)> {
+    let storage = warp::any().map(move || storage.clone());
+
+    let games = warp::path!("api" / "games")
+        .and(warp::query::<GamesQuery>())
+        .and(storage.clone())
+        .and_then(|query, storage| async move { respond(list_games(query, storage).await) });
+
+    let game = warp::path!("api" / "games" / Uuid)
+        .and(storage.clone())
+        .and_then(|id, storage| async move { respond(get_game(id, storage).await) });
+
+    let game_ptn = warp::path!("api" / "games" / Uuid / "ptn")
+        .and(storage.clone())
+        .and_then(|id, storage| async move { respond(download_game(id, storage).await) });
+
+    let export = warp::path!("api" / "export")
+        .and(warp::query::<ExportQuery>())
+        .and(storage.clone())
+        .and_then(|query, storage| async move { respond(export_games(query, storage).await) });
+
+    let player = warp::path!("api" / "players" / String)
+        .and(storage.clone())
+        .and_then(|name, storage| async move { respond(get_player(name, storage).await) });
+
+    let leaderboard = warp::path!("api" / "leaderboard")
+        .and(warp::query::<PageQuery>())
+        .and(storage)
+        .and_then(|query, storage| async move { respond(leaderboard(query, storage).await) });
+
+    let cors = warp::cors().allow_any_origin().allow_method("GET");
+    warp::get()
+        .and(games.or(game).unify().or(game_ptn).unify().or(export).unify().or(player).unify().or(leaderboard).unify())
+        .with(cors)
+        .map(|reply| Box::new(reply) as Box<dyn Reply>)
+        .boxed()
+}
+
+async fn list_games(query: GamesQuery, storage: Arc<dyn Storage>) -> Result<Box<dyn Reply>, Error> {
+    let (limit, offset) = page(query.limit, query.offset);
+    let filter = GameFilter {
+        player: query.player,
+        size: query.size,
+        rated: query.rated,
+        before: query.before,
+        after: query.after,
+        limit,
+        offset,
+    };
+    let games = storage.list_games(filter).await?;
+    Ok(Box::new(warp::reply::json(&GamesPage {
+        games: games.iter().map(GameSummary::new).collect(),
+        limit,
+        offset,
+    })))
+}
+
+async fn get_game(id: Uuid, storage: Arc<dyn Storage>) -> Result<Box<dyn Reply>, Error> {
+    let game = match storage.get_game(id).await? {
+        Some(game) => game,
+        None => return Ok(json_error("game-not-found", StatusCode::NOT_FOUND)),
+    };
+    Ok(Box::new(warp::reply::json(&GameDetail {
+        summary: GameSummary::new(&game),
+        moves: game.notation.split_whitespace().map(String::from).collect(),
+        ptn: ptn::document(&game),
+    })))
+}
+
+fn attachment(body: Body, content_type: &str, file_name: &str) -> Box<dyn Reply> {
+    let response = Response::builder()
+        .header("content-type", content_type)
+        .header("content-disposition", format!("attachment; filename=\"{}\"", file_name))
+        .body(body);
+    match response {
+        Ok(response) => Box::new(response),
+        Err(err) => {
+            error!(error = %err, "Could not build download");
+            json_error("server-error", StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn download_game(id: Uuid, storage: Arc<dyn Storage>) -> Result<Box<dyn Reply>, Error> {
+    match storage.get_game(id).await? {
+        Some(game) => Ok(attachment(Body::from(ptn::document(&game)), PTN_CONTENT_TYPE, &ptn::file_name(&game))),
+        None => Ok(json_error("game-not-found", StatusCode::NOT_FOUND)),
+    }
+}
+
+//every game for a player and or date range, as one ptn stream or a zip with a file per game
+async fn export_games(query: ExportQuery, storage: Arc<dyn Storage>) -> Result<Box<dyn Reply>, Error> {
+    if query.player.is_none() && query.before.is_none() && query.after.is_none() {
+        return Ok(json_error("missing-filter", StatusCode::BAD_REQUEST));
+    }
+    let filter = GameFilter {
+        player: query.player,
+        before: query.before,
+        after: query.after,
+        limit: MAX_PAGE_SIZE,
+        ..GameFilter::default()
+    };
+
+    match query.format.unwrap_or(ExportFormat::Ptn) {
+        ExportFormat::Ptn => {
+            //pages are fetched as the client reads, so exports of any size stream in constant memory
+            let pages = stream::unfold(Some(filter), move |filter| {
+                let storage = storage.clone();
+                async move {
+                    let mut filter = filter?;
+                    let games = match storage.list_games(filter.clone()).await {
+                        Ok(games) => games,
+                        Err(err) => return Some((Err(err), None)),
+                    };
+                    if games.is_empty() {
+                        return None;
+                    }
+                    let page: String = games.iter().map(|game| ptn::document(game) + "\n").collect();
+                    let next = if games.len() < filter.limit as usize {
+                        None
+                    } else {
+                        filter.offset += filter.limit;
+                        Some(filter)
+                    };
+                    Some((Ok(page), next))
+                }
+            });
+            Ok(attachment(Body::wrap_stream(pages), PTN_CONTENT_TYPE, "games.ptn"))
+        },
+        ExportFormat::Zip => {
+            let mut filter = filter;
+            let mut games = Vec::new();
+            loop {
+                let page = storage.list_games(filter.clone()).await?;
+                let last_page = page.len() < filter.limit as usize;
+                games.extend(page);
+                if games.len() > MAX_ZIP_GAMES {
+                    return Ok(json_error("too-many-games", StatusCode::PAYLOAD_TOO_LARGE));
+                }
+                if last_page {
+                    break;
+                }
+                filter.offset += filter.limit;
+            }
+            let archive = task::spawn_blocking(move || zip_games(&games))
+                .await
+                .map_err(|err| Error::System(err.to_string()))??;
+            Ok(attachment(Body::from(archive), "application/zip", "games.zip"))
+        }
+    }
+}
+
+fn zip_games(games: &[GameRecord]) -> Result<Vec<u8>, Error> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for game in games {
+        archive.start_file(ptn::file_name(game), options).map_err(|err| Error::System(err.to_string()))?;
+        archive.write_all(ptn::document(game).as_bytes()).map_err(|err| Error::System(err.to_string()))?;
+    }
+    let archive = archive.finish().map_err(|err| Error::System(err.to_string()))?;
+    Ok(archive.into_inner())
+}
+
+async fn get_player(name: String, storage: Arc<dyn Storage>) -> Result<Box<dyn Reply>, Error> {
+    let player: Player = match storage.get_user(&name).await? {
+        Some(player) => player,
+        None => return Ok(json_error("player-not-found", StatusCode::NOT_FOUND)),
+    };
+    let history = storage.rating_history(player.id).await?;
+    Ok(Box::new(warp::reply::json(&PlayerProfile {
+        name: player.name,
+        rating: player.stats.rating,
+        games_played: player.stats.games_played(),
+        wins: player.stats.wins,
+        losses: player.stats.losses,
+        draws: player.stats.draws,
+        joined: player.joined,
+        last_seen: player.last_seen,
+        rating_history: history.iter().map(RatingPoint::new).collect(),
+    })))
+}
+
+async fn leaderboard(query: PageQuery, storage: Arc<dyn Storage>) -> Result<Box<dyn Reply>, Error> {
+    let (limit, offset) = page(query.limit, query.offset);
+    let players = storage.leaderboard(limit, offset).await?;
+    let entries: Vec<LeaderboardEntry> = players.iter().enumerate().map(|(index, player)| LeaderboardEntry {
+        rank: offset + index as u32 + 1,
+        name: player.name.clone(),
+        rating: player.stats.rating,
+        games_played: player.stats.games_played(),
+    }).collect();
+    Ok(Box::new(warp::reply::json(&entries)))
+}
@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+use std::net::IpAddr;
+use crate::tak::player::Player;
+
+//lets an embedding application veto registrations and sign ins, for example to admit only tournament entrants
+//both checks run after the server's own validation, a refusal is reported to the client as `not-allowed`
+#[async_trait]
+pub trait AuthHook: Send + Sync {
+    async fn allow_register(&self, _name: &str, _email: &str, _ip: Option<IpAddr>) -> bool {
+        true
+    }
+
+    //called for accounts and guests alike, once their credentials have checked out
+    async fn allow_sign_in(&self, _player: &Player, _ip: Option<IpAddr>) -> bool {
+        true
+    }
+}
+
+//the default hook, admits everyone the server itself admits
+pub struct AllowAll;
+
+impl AuthHook for AllowAll {}
@@ -1,14 +1,17 @@
 use uuid::Uuid;
+use std::net::IpAddr;
 
 #[derive(Clone, Copy, Default)]
 pub struct Client {
     pub id: Uuid,
+    pub ip: Option<IpAddr>,
 }
 
 impl Client{
-    pub fn new() -> Self {
+    pub fn new(ip: Option<IpAddr>) -> Self {
         Client{
-            id: Uuid::new_v4()
+            id: Uuid::new_v4(),
+            ip,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,26 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn unix_time() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+//the utc calendar date and time of day of a unix timestamp, as (year, month, day, seconds into the day)
+pub fn utc_date(time: i64) -> (i64, u32, u32, u32) {
+    let days = time.div_euclid(86400);
+    let seconds = time.rem_euclid(86400) as u32;
+
+    //civil from days, counting from 0000-03-01 so leap days fall at the end of each year
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = (if month_index < 10 { month_index + 3 } else { month_index - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, seconds)
+}
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use crate::error::Error;
+use crate::database;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    pub storage: StorageKind,
+    pub database_path: String,
+    pub database_connections: usize,
+    pub max_connections_per_ip: usize,
+    pub rate_limits: RateLimits,
+    pub login_protection: LoginProtection,
+    //secret used to sign session tokens, a random one is generated on start up when unset,
+    //which the server binary only allows with memory storage
+    pub token_secret: Option<String>,
+    pub token_lifetime: u64,
+    pub log_format: LogFormat,
+    //a practice bot hosted by the hub, off when unset
+    pub bot: Option<BotConfig>,
+}
+
+impl Config {
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path).map_err(|err| Error::System(err.to_string()))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            storage: StorageKind::Sqlite,
+            database_path: String::from(database::DEFAULT_PATH),
+            database_connections: database::DEFAULT_POOL_SIZE,
+            max_connections_per_ip: 8,
+            rate_limits: RateLimits::default(),
+            login_protection: LoginProtection::default(),
+            token_secret: None,
+            token_lifetime: 30 * 24 * 60 * 60,
+            log_format: LogFormat::Text,
+            bot: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StorageKind {
+    Sqlite,
+    //nothing survives a restart, for tests and throwaway dev servers
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogFormat {
+    Text,
+    //one json object per line, for log collectors
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RateLimits {
+    pub register: InputLimits,
+    pub sign_in: InputLimits,
+    pub post: InputLimits,
+    pub guest_post: InputLimits,
+    pub lookup: InputLimits,
+    pub seek: InputLimits,
+    pub play: InputLimits,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            register: InputLimits::new(RateLimit::new(3, 60), RateLimit::new(10, 600)),
+            sign_in: InputLimits::new(RateLimit::new(5, 60), RateLimit::new(20, 60)),
+            post: InputLimits::new(RateLimit::new(10, 10), RateLimit::new(40, 10)),
+            guest_post: InputLimits::new(RateLimit::new(3, 10), RateLimit::new(12, 10)),
+            lookup: InputLimits::new(RateLimit::new(20, 10), RateLimit::new(60, 10)),
+            seek: InputLimits::new(RateLimit::new(10, 10), RateLimit::new(40, 10)),
+            play: InputLimits::new(RateLimit::new(20, 2), RateLimit::new(80, 2)),
+        }
+    }
+}
+
+//limits applied to a single input kind, once per client and once per ip address
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputLimits {
+    pub client: RateLimit,
+    pub ip: RateLimit,
+}
+
+impl InputLimits {
+    pub fn new(client: RateLimit, ip: RateLimit) -> Self {
+        InputLimits {
+            client,
+            ip,
+        }
+    }
+}
+
+//the seek the bot keeps open and how hard it thinks, `think_time` is in milliseconds per move
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BotConfig {
+    pub name: String,
+    pub size: u8,
+    pub time: u32,
+    pub increment: u32,
+    pub komi: u8,
+    pub depth: u32,
+    pub think_time: u64,
+    //an external TEI engine to play instead of the built in search
+    pub engine: Option<EngineConfig>,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            name: String::from("PracticeBot"),
+            size: 5,
+            time: 10 * 60,
+            increment: 10,
+            komi: 0,
+            depth: 3,
+            think_time: 2000,
+            engine: None,
+        }
+    }
+}
+
+//a local engine process speaking TEI over stdin and stdout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+//token bucket holding up to `burst` tokens, refilled with `burst` tokens every `per_seconds`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_seconds: u64,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, per_seconds: u64) -> Self {
+        RateLimit {
+            burst,
+            per_seconds,
+        }
+    }
+}
+
+//failed sign in handling, all durations are in seconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LoginProtection {
+    pub window: u64,
+    pub free_attempts: u32,
+    pub backoff_base: u64,
+    pub max_backoff: u64,
+    pub lockout_threshold: u32,
+    pub lockout_duration: u64,
+    pub ip_lockout_threshold: u32,
+}
+
+impl Default for LoginProtection {
+    fn default() -> Self {
+        LoginProtection {
+            window: 15 * 60,
+            free_attempts: 3,
+            backoff_base: 2,
+            max_backoff: 5 * 60,
+            lockout_threshold: 10,
+            lockout_duration: 15 * 60,
+            ip_lockout_threshold: 30,
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use log::{debug, warn};
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::clock::unix_time;
+use crate::database::migrations;
+use crate::error::Error;
+use crate::model::game::GameRecord;
+use crate::names::{name_key, name_skeleton};
+use crate::tak::player::{Stats, DEFAULT_RATING};
+use crate::tak::{ptn, rating};
+use crate::tak::board::GameOptions;
+
+//imports accounts and finished games from the legacy PlayTak server's players and games tables
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub players: usize,
+    pub flagged_for_reset: usize,
+    pub skipped_players: usize,
+    pub games: usize,
+    pub skipped_games: usize,
+}
+
+struct ImportedPlayer {
+    id: Uuid,
+    stats: Stats,
+}
+
+//the rust bcrypt crate verifies every variant the legacy server could have written
+fn is_compatible_hash(password: &str) -> bool {
+    ["$2a$", "$2b$", "$2y$"].iter().any(|prefix| password.starts_with(prefix))
+}
+
+fn has_column(db: &Connection, table: &str, column: &str) -> Result<bool, Error> {
+    let mut columns = db.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = columns.query_map(params![], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name?.eq_ignore_ascii_case(column) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+//older legacy databases lack some columns, fall back to a constant for those
+fn column_or(db: &Connection, table: &str, column: &str, default: &str) -> Result<String, Error> {
+    Ok(if has_column(db, table, column)? { String::from(column) } else { String::from(default) })
+}
+
+pub fn import(target_path: &str, players_path: &str, games_path: &str) -> Result<ImportSummary, Error> {
+    let mut target = Connection::open(target_path)?;
+    migrations::run(&mut target, unix_time())?;
+
+    let legacy_players = Connection::open(players_path)?;
+    let legacy_games = Connection::open(games_path)?;
+
+    let mut summary = ImportSummary::default();
+    let transaction = target.transaction()?;
+    let mut players = import_players(&transaction, &legacy_players, &mut summary)?;
+    import_games(&transaction, &legacy_games, &mut players, &mut summary)?;
+
+    for (name, player) in &players {
+        transaction.execute("UPDATE players SET rating = ?1, wins = ?2, losses = ?3, draws = ?4 WHERE uuid = ?5",
+            params![player.stats.rating, player.stats.wins, player.stats.losses, player.stats.draws, player.id.to_string()])?;
+        debug!("Rebuilt {} at {:.0} after {} games", name, player.stats.rating, player.stats.games_played());
+    }
+    transaction.commit()?;
+
+    Ok(summary)
+}
+
+fn import_players(target: &Transaction, legacy: &Connection, summary: &mut ImportSummary) -> Result<HashMap<String, ImportedPlayer>, Error> {
+    let email = column_or(legacy, "players", "email", "''")?;
+    let mut legacy_players = legacy.prepare(&format!("SELECT name, password, {} FROM players ORDER BY id", email))?;
+    let rows = legacy_players.query_map(params![], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?))
+    })?;
+
+    let mut players = HashMap::new();
+    for row in rows {
+        let (name, password, email) = row?;
+
+        let existing: Option<String> = target.query_row("SELECT uuid FROM players WHERE name_key = ?1",
+            params![name_key(&name)], |row| row.get(0)).optional()?;
+        if existing.is_some() {
+            warn!("Skipping legacy player {}, the name is already taken", name);
+            summary.skipped_players += 1;
+            continue;
+        }
+
+        //empty and duplicate addresses would break the unique email column
+        let email = email.map(|email| email.trim().to_string()).filter(|email| !email.is_empty());
+        let email = match email {
+            Some(email) => {
+                let taken: Option<i64> = target.query_row("SELECT id FROM players WHERE email = ?1",
+                    params![email], |row| row.get(0)).optional()?;
+                if taken.is_some() { None } else { Some(email) }
+            },
+            None => None,
+        };
+
+        let password = password.unwrap_or_default();
+        let password_reset = !is_compatible_hash(&password);
+        let password = if password_reset { String::new() } else { password };
+
+        let id = Uuid::new_v4();
+        target.execute("INSERT INTO players (uuid, name, name_key, name_skeleton, password, email, password_reset)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![id.to_string(), name, name_key(&name), name_skeleton(&name), password, email, password_reset])?;
+
+        summary.players += 1;
+        if password_reset {
+            summary.flagged_for_reset += 1;
+        }
+        players.insert(name, ImportedPlayer {
+            id,
+            stats: Stats::default(),
+        });
+    }
+    Ok(players)
+}
+
+fn import_games(target: &Transaction, legacy: &Connection, players: &mut HashMap<String, ImportedPlayer>, summary: &mut ImportSummary) -> Result<(), Error> {
+    let time = column_or(legacy, "games", "timertime", "0")?;
+    let increment = column_or(legacy, "games", "timerinc", "0")?;
+    let unrated = column_or(legacy, "games", "unrated", "0")?;
+    let komi = column_or(legacy, "games", "komi", "0")?;
+    let pieces = column_or(legacy, "games", "pieces", "NULL")?;
+    let capstones = column_or(legacy, "games", "capstones", "NULL")?;
+    let mut legacy_games = legacy.prepare(&format!("SELECT id, date, size, player_white, player_black, notation, result, {}, {}, {}, {}, {}, {}
+        FROM games ORDER BY date, id", time, increment, unrated, komi, pieces, capstones))?;
+    let rows = legacy_games.query_map(params![], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, u8>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<u32>>(7)?.unwrap_or(0),
+            row.get::<_, Option<u32>>(8)?.unwrap_or(0),
+            row.get::<_, Option<bool>>(9)?.unwrap_or(false),
+            row.get::<_, Option<u32>>(10)?.unwrap_or(0),
+            row.get::<_, Option<u8>>(11)?,
+            row.get::<_, Option<u8>>(12)?,
+        ))
+    })?;
+
+    for row in rows {
+        let (legacy_id, date, size, white, black, notation, result, time, increment, unrated, komi, pieces, capstones) = row?;
+
+        let notation = match notation.as_deref().map(ptn::from_server_notation) {
+            Some(Some(notation)) => notation,
+            _ => {
+                warn!("Skipping legacy game {}, its notation could not be converted", legacy_id);
+                summary.skipped_games += 1;
+                continue;
+            }
+        };
+
+        let standard = GameOptions::standard(size);
+        let white_rating = players.get(&white).map_or(DEFAULT_RATING, |player| player.stats.rating);
+        let black_rating = players.get(&black).map_or(DEFAULT_RATING, |player| player.stats.rating);
+        let rated = !unrated && players.contains_key(&white) && players.contains_key(&black);
+        let game = GameRecord {
+            id: Uuid::new_v4(),
+            //the legacy server stored milliseconds
+            date: date / 1000,
+            size,
+            white,
+            black,
+            notation,
+            result: result.unwrap_or_default(),
+            rated,
+            white_rating,
+            black_rating,
+            time,
+            increment,
+            //the legacy server counted komi in half flats
+            komi: komi as f64 / 2.0,
+            //older rows left the counts empty for standard games
+            stones: pieces.or(standard.map(|standard| standard.stones)).unwrap_or(0),
+            caps: capstones.or(standard.map(|standard| standard.caps)).unwrap_or(0),
+            no_swap: false,
+            event: None,
+            round: None,
+        };
+
+        target.execute("INSERT INTO games
+            (uuid, date, size, white, black, notation, result, rated, white_rating, black_rating, time, increment, komi, stones, caps) VALUES
+            (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![game.id.to_string(), game.date, game.size, game.white, game.black, game.notation,
+                game.result, game.rated, game.white_rating, game.black_rating, game.time, game.increment, game.komi,
+                game.stones, game.caps])?;
+        summary.games += 1;
+
+        if let (true, Some(white_score)) = (game.rated, game.white_score()) {
+            rate(target, players, &game, &game.white, white_score, black_rating)?;
+            rate(target, players, &game, &game.black, 1.0 - white_score, white_rating)?;
+        }
+    }
+    Ok(())
+}
+
+fn rate(target: &Transaction, players: &mut HashMap<String, ImportedPlayer>, game: &GameRecord, name: &str, score: f64, opponent_rating: f64) -> Result<(), Error> {
+    let player = match players.get_mut(name) {
+        Some(player) => player,
+        None => return Ok(()),
+    };
+
+    player.stats.rating = rating::updated(player.stats.rating, opponent_rating, score);
+    if score > 0.5 {
+        player.stats.wins += 1;
+    } else if score < 0.5 {
+        player.stats.losses += 1;
+    } else {
+        player.stats.draws += 1;
+    }
+
+    target.execute("INSERT INTO ratings (player_uuid, game_uuid, rating, time) VALUES (?1, ?2, ?3, ?4)",
+        params![player.id.to_string(), game.id.to_string(), player.stats.rating, game.date])?;
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use log::info;
+use crate::error::Error;
+use crate::clock::unix_time;
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+//append new steps to the end, never edit a step that has already shipped
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create players table",
+        sql: "CREATE TABLE if not exists players (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            uuid VARCHAR UNIQUE,
+            name VARCHAR UNIQUE,
+            password VARCHAR,
+            email VARCHAR UNIQUE
+        );",
+    },
+    Migration {
+        version: 2,
+        description: "track sign in attempts",
+        sql: "CREATE TABLE if not exists login_attempts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name VARCHAR,
+            ip VARCHAR,
+            success BOOLEAN,
+            time INTEGER
+        );
+        CREATE INDEX if not exists login_attempts_name ON login_attempts (name, time);
+        CREATE INDEX if not exists login_attempts_ip ON login_attempts (ip, time);",
+    },
+    Migration {
+        version: 3,
+        description: "store session token hashes",
+        sql: "CREATE TABLE if not exists sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            token_hash VARCHAR UNIQUE,
+            player_uuid VARCHAR,
+            expires INTEGER,
+            revoked BOOLEAN DEFAULT 0
+        );",
+    },
+    Migration {
+        version: 4,
+        description: "add player stats and timestamps",
+        sql: "ALTER TABLE players ADD COLUMN rating REAL DEFAULT 1000;
+        ALTER TABLE players ADD COLUMN wins INTEGER DEFAULT 0;
+        ALTER TABLE players ADD COLUMN losses INTEGER DEFAULT 0;
+        ALTER TABLE players ADD COLUMN draws INTEGER DEFAULT 0;
+        ALTER TABLE players ADD COLUMN joined INTEGER DEFAULT 0;
+        ALTER TABLE players ADD COLUMN last_seen INTEGER DEFAULT 0;",
+    },
+    Migration {
+        version: 5,
+        description: "create games and rating history tables",
+        sql: "CREATE TABLE if not exists games (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            uuid VARCHAR UNIQUE,
+            date INTEGER,
+            size INTEGER,
+            white VARCHAR,
+            black VARCHAR,
+            notation TEXT,
+            result VARCHAR,
+            rated BOOLEAN,
+            white_rating REAL,
+            black_rating REAL,
+            time INTEGER,
+            increment INTEGER
+        );
+        CREATE INDEX if not exists games_white ON games (white, date);
+        CREATE INDEX if not exists games_black ON games (black, date);
+        CREATE TABLE if not exists ratings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            player_uuid VARCHAR,
+            game_uuid VARCHAR,
+            rating REAL,
+            time INTEGER
+        );
+        CREATE INDEX if not exists ratings_player ON ratings (player_uuid, time);",
+    },
+    Migration {
+        version: 6,
+        description: "flag accounts that must reset their password",
+        sql: "ALTER TABLE players ADD COLUMN password_reset BOOLEAN DEFAULT 0;",
+    },
+    Migration {
+        version: 7,
+        description: "add normalized name keys and skeletons",
+        sql: "ALTER TABLE players ADD COLUMN name_key VARCHAR;
+        ALTER TABLE players ADD COLUMN name_skeleton VARCHAR;
+        UPDATE players SET name_key = lower(trim(name)),
+            name_skeleton = replace(replace(replace(replace(replace(replace(replace(replace(
+                lower(trim(name)), '_', ''), ' ', ''), 'rn', 'm'), 'vv', 'w'), '0', 'o'), '1', 'l'), 'i', 'l'), '5', 's');
+        CREATE UNIQUE INDEX if not exists players_name_key ON players (name_key);
+        CREATE INDEX if not exists players_name_skeleton ON players (name_skeleton);",
+    },
+    Migration {
+        version: 8,
+        description: "index games by date and players by rating",
+        sql: "CREATE INDEX if not exists games_date ON games (date);
+        CREATE INDEX if not exists players_rating ON players (rating);",
+    },
+    Migration {
+        version: 9,
+        description: "record komi and tournament rounds on games",
+        sql: "ALTER TABLE games ADD COLUMN komi REAL DEFAULT 0;
+        ALTER TABLE games ADD COLUMN event VARCHAR;
+        ALTER TABLE games ADD COLUMN round VARCHAR;",
+    },
+    Migration {
+        version: 10,
+        description: "record piece counts on games",
+        sql: "ALTER TABLE games ADD COLUMN stones INTEGER;
+        ALTER TABLE games ADD COLUMN caps INTEGER;
+        UPDATE games SET
+            stones = CASE size WHEN 3 THEN 10 WHEN 4 THEN 15 WHEN 5 THEN 21 WHEN 6 THEN 30 WHEN 7 THEN 40 ELSE 50 END,
+            caps = CASE size WHEN 3 THEN 0 WHEN 4 THEN 0 WHEN 5 THEN 1 WHEN 6 THEN 1 ELSE 2 END;",
+    },
+    Migration {
+        version: 11,
+        description: "record games played without the swap opening",
+        sql: "ALTER TABLE games ADD COLUMN no_swap BOOLEAN DEFAULT 0;",
+    },
+    Migration {
+        version: 12,
+        description: "add one time password reset tokens",
+        sql: "CREATE TABLE if not exists password_resets (
+            token_hash VARCHAR PRIMARY KEY,
+            player_uuid VARCHAR,
+            expires INTEGER
+        );",
+    },
+    Migration {
+        version: 13,
+        description: "index games by player names regardless of case",
+        sql: "CREATE INDEX if not exists games_white_nocase ON games (white COLLATE NOCASE, date);
+        CREATE INDEX if not exists games_black_nocase ON games (black COLLATE NOCASE, date);",
+    },
+];
+
+fn ensure_version_table(db: &Connection) -> Result<(), Error> {
+    db.execute("CREATE TABLE if not exists schema_version (
+        version INTEGER PRIMARY KEY,
+        description VARCHAR,
+        applied INTEGER
+    )", params![])?;
+    Ok(())
+}
+
+pub fn current_version(db: &Connection) -> Result<u32, Error> {
+    ensure_version_table(db)?;
+    let version: Option<u32> = db.query_row("SELECT MAX(version) FROM schema_version", params![], |row| row.get(0))
+        .optional()?
+        .flatten();
+    Ok(version.unwrap_or(0))
+}
+
+pub fn pending(db: &Connection) -> Result<Vec<&'static Migration>, Error> {
+    let version = current_version(db)?;
+    Ok(MIGRATIONS.iter().filter(|migration| migration.version > version).collect())
+}
+
+//applies every pending step in order, each in its own transaction, returning how many ran
+pub fn run(db: &mut Connection, now: i64) -> Result<usize, Error> {
+    let pending: Vec<&Migration> = pending(db)?;
+    for migration in &pending {
+        info!("Applying migration {}: {}", migration.version, migration.description);
+        let transaction = db.transaction()?;
+        transaction.execute_batch(migration.sql)?;
+        transaction.execute("INSERT INTO schema_version (version, description, applied) VALUES (?1, ?2, ?3)",
+            params![migration.version, migration.description, now])?;
+        transaction.commit()?;
+    }
+    Ok(pending.len())
+}
+
+//entry points for the `migrate` command, which works on a database file without starting the server
+pub fn status(path: &str) -> Result<(u32, Vec<&'static Migration>), Error> {
+    let db = Connection::open(path)?;
+    Ok((current_version(&db)?, pending(&db)?))
+}
+
+pub fn run_pending(path: &str) -> Result<usize, Error> {
+    let mut db = Connection::open(path)?;
+    run(&mut db, unix_time())
+}
@@ -0,0 +1,377 @@
+use rusqlite::{params, Connection, OptionalExtension, Row, ToSql};
+use log::{info, error};
+use uuid::Uuid;
+use std::str::FromStr;
+// use std::error::Error;
+use crate::error::Error;
+use crate::tak::player::{Player, Stats};
+use crate::model::game::{GameFilter, GameRecord, RatingEntry};
+use crate::storage::Storage;
+use crate::names::{name_key, name_skeleton};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::net::IpAddr;
+use tokio::task;
+use crate::clock::unix_time;
+use crate::metrics::METRICS;
+use std::time::Instant;
+
+mod pool;
+pub mod migrations;
+pub mod legacy;
+
+use pool::Pool;
+
+pub const DEFAULT_PATH: &str = "playtak_data.db";
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+const PLAYER_COLUMNS: &str = "uuid, name, password, email, rating, wins, losses, draws, joined, last_seen, password_reset";
+
+const GAME_COLUMNS: &str = "uuid, date, size, white, black, notation, result, rated, white_rating, black_rating, time, increment, komi, event, round, stones, caps, no_swap";
+
+fn uuid_from_column(row: &Row, index: usize) -> rusqlite::Result<Uuid> {
+    let uuid_string: String = row.get(index)?;
+    Uuid::from_str(&uuid_string)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(err)))
+}
+
+fn game_from_row(row: &Row) -> rusqlite::Result<GameRecord> {
+    Ok(GameRecord {
+        id: uuid_from_column(row, 0)?,
+        date: row.get(1)?,
+        size: row.get(2)?,
+        white: row.get(3)?,
+        black: row.get(4)?,
+        notation: row.get(5)?,
+        result: row.get(6)?,
+        rated: row.get(7)?,
+        white_rating: row.get(8)?,
+        black_rating: row.get(9)?,
+        time: row.get(10)?,
+        increment: row.get(11)?,
+        komi: row.get(12)?,
+        event: row.get(13)?,
+        round: row.get(14)?,
+        stones: row.get(15)?,
+        caps: row.get(16)?,
+        no_swap: row.get(17)?,
+    })
+}
+
+fn player_from_row(row: &Row) -> rusqlite::Result<Player> {
+    let mut player = Player::existing(uuid_from_column(row, 0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+    player.stats = Stats {
+        rating: row.get(4)?,
+        wins: row.get(5)?,
+        losses: row.get(6)?,
+        draws: row.get(7)?,
+    };
+    player.joined = row.get(8)?;
+    player.last_seen = row.get(9)?;
+    player.password_reset = row.get(10)?;
+    Ok(player)
+}
+
+//every query runs on tokio's blocking thread pool with a pooled connection, statements are cached per connection
+#[derive(Clone)]
+pub struct Database{
+    pool: Arc<Pool>,
+    is_setup: bool,
+}
+
+impl Database{
+    pub fn open(path: &str, pool_size: usize) -> Self{
+        let pool = Arc::new(Pool::new(path, pool_size));
+        let is_setup = match pool.get().and_then(|mut db| Database::set_up(&mut db)) {
+            Ok(()) => true,
+            Err(err) => {
+                error!("Error setting up database: {}", err);
+                false
+            }
+        };
+        Database{
+            pool,
+            is_setup,
+        }
+    }
+
+    pub fn set_up(db: &mut Connection) -> Result<(), Error>{
+        let applied = migrations::run(db, unix_time())?;
+        if applied > 0 {
+            info!("Applied {} database migrations", applied);
+        }
+        Ok(())
+    }
+
+    //runs `work` with a pooled connection without blocking the async runtime
+    async fn run<T, F>(&self, work: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        if !self.is_setup {
+            return Err(Error::System(String::from("Database not yet setup!")));
+        }
+        let pool = self.pool.clone();
+        task::spawn_blocking(move || {
+            let started = Instant::now();
+            let result = pool.get().and_then(|db| work(&db));
+            METRICS.database_query(started.elapsed(), result.is_err());
+            result
+        }).await.map_err(|err| Error::System(err.to_string()))?
+    }
+}
+
+#[async_trait]
+impl Storage for Database{
+    fn is_setup(&self) -> bool {
+        self.is_setup
+    }
+
+    async fn get_user(&self, user_name: &str) -> Result<Option<Player>, Error> {
+        let user_name = String::from(user_name);
+        self.run(move |db| {
+            let mut players_with_name = db.prepare_cached(&format!("SELECT {} FROM players WHERE name_key = ?1", PLAYER_COLUMNS))?;
+            Ok(players_with_name.query_row(params![name_key(&user_name)], player_from_row).optional()?)
+        }).await
+    }
+
+    async fn similar_name_exists(&self, user_name: &str) -> Result<bool, Error> {
+        let skeleton = name_skeleton(user_name);
+        self.run(move |db| {
+            let similar: Option<i64> = db.prepare_cached("SELECT id FROM players WHERE name_skeleton = ?1 LIMIT 1")?
+                .query_row(params![skeleton], |row| row.get(0))
+                .optional()?;
+            Ok(similar.is_some())
+        }).await
+    }
+
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<Player>, Error> {
+        self.run(move |db| {
+            let mut players_with_id = db.prepare_cached(&format!("SELECT {} FROM players WHERE uuid = ?1", PLAYER_COLUMNS))?;
+            Ok(players_with_id.query_row(params![id.to_string()], player_from_row).optional()?)
+        }).await
+    }
+
+    async fn write_player(&self, player: Player) -> Result<bool, Error> {
+        self.run(move |db| {
+            let hashed_pass = Player::hash_password(player.password)
+                .map_err(|_| Error::System(String::from("error hashing password")))?;
+
+            let mut insert_player = db.prepare_cached("INSERT INTO players
+                (uuid, name, name_key, name_skeleton, password, email, joined, last_seen) VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)")?;
+            let res = insert_player.execute(params![player.id.to_string(), player.name, name_key(&player.name),
+                name_skeleton(&player.name), hashed_pass, player.email, player.joined])?;
+
+            Ok(res > 0)
+        }).await
+    }
+
+    async fn update_last_seen(&self, player_id: Uuid, time: i64) -> Result<(), Error> {
+        self.run(move |db| {
+            db.prepare_cached("UPDATE players SET last_seen = ?1 WHERE uuid = ?2")?
+                .execute(params![time, player_id.to_string()])?;
+            Ok(())
+        }).await
+    }
+
+    async fn update_password(&self, player_id: Uuid, password: String) -> Result<(), Error> {
+        self.run(move |db| {
+            let hashed_pass = Player::hash_password(password)
+                .map_err(|_| Error::System(String::from("error hashing password")))?;
+            db.prepare_cached("UPDATE players SET password = ?1, password_reset = 0 WHERE uuid = ?2")?
+                .execute(params![hashed_pass, player_id.to_string()])?;
+            Ok(())
+        }).await
+    }
+
+    async fn update_stats(&self, player_id: Uuid, stats: Stats) -> Result<(), Error> {
+        self.run(move |db| {
+            db.prepare_cached("UPDATE players SET rating = ?1, wins = ?2, losses = ?3, draws = ?4 WHERE uuid = ?5")?
+                .execute(params![stats.rating, stats.wins, stats.losses, stats.draws, player_id.to_string()])?;
+            Ok(())
+        }).await
+    }
+
+    async fn leaderboard(&self, limit: u32, offset: u32) -> Result<Vec<Player>, Error> {
+        self.run(move |db| {
+            let mut ranked = db.prepare_cached(&format!("SELECT {} FROM players WHERE wins + losses + draws > 0
+                ORDER BY rating DESC, name_key LIMIT ?1 OFFSET ?2", PLAYER_COLUMNS))?;
+            let players = ranked.query_map(params![limit, offset], player_from_row)?;
+            Ok(players.collect::<rusqlite::Result<Vec<Player>>>()?)
+        }).await
+    }
+
+    async fn write_session(&self, token_hash: &str, player_id: Uuid, expires: i64) -> Result<(), Error> {
+        let token_hash = String::from(token_hash);
+        self.run(move |db| {
+            db.prepare_cached("INSERT INTO sessions (token_hash, player_uuid, expires) VALUES (?1, ?2, ?3)")?
+                .execute(params![token_hash, player_id.to_string(), expires])?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_session_user(&self, token_hash: &str, now: i64) -> Result<Option<Player>, Error> {
+        let token_hash = String::from(token_hash);
+        self.run(move |db| {
+            let mut session_player = db.prepare_cached(&format!("SELECT {} FROM players
+                WHERE uuid = (SELECT player_uuid FROM sessions WHERE token_hash = ?1 AND expires > ?2 AND revoked = 0)",
+                PLAYER_COLUMNS))?;
+            Ok(session_player.query_row(params![token_hash, now], player_from_row).optional()?)
+        }).await
+    }
+
+    async fn revoke_sessions(&self, player_id: Uuid) -> Result<(), Error> {
+        self.run(move |db| {
+            db.prepare_cached("UPDATE sessions SET revoked = 1 WHERE player_uuid = ?1")?
+                .execute(params![player_id.to_string()])?;
+            Ok(())
+        }).await
+    }
+
+    async fn write_password_reset(&self, token_hash: &str, player_id: Uuid, expires: i64) -> Result<(), Error> {
+        let token_hash = String::from(token_hash);
+        self.run(move |db| {
+            db.prepare_cached("INSERT INTO password_resets (token_hash, player_uuid, expires) VALUES (?1, ?2, ?3)")?
+                .execute(params![token_hash, player_id.to_string(), expires])?;
+            Ok(())
+        }).await
+    }
+
+    async fn take_password_reset(&self, token_hash: &str, now: i64) -> Result<Option<Uuid>, Error> {
+        let token_hash = String::from(token_hash);
+        self.run(move |db| {
+            let reset = db.prepare_cached("SELECT player_uuid, expires FROM password_resets WHERE token_hash = ?1")?
+                .query_row(params![token_hash], |row| Ok((uuid_from_column(row, 0)?, row.get::<_, i64>(1)?)))
+                .optional()?;
+            //whoever deletes the row owns the token, a second redeemer finds nothing to delete
+            let deleted = db.prepare_cached("DELETE FROM password_resets WHERE token_hash = ?1")?
+                .execute(params![token_hash])?;
+            Ok(reset.filter(|(_, expires)| deleted > 0 && *expires > now).map(|(player_id, _)| player_id))
+        }).await
+    }
+
+    async fn record_login_attempt(&self, user_name: &str, ip: Option<IpAddr>, success: bool, time: i64) -> Result<(), Error> {
+        let user_name = name_key(user_name);
+        self.run(move |db| {
+            db.prepare_cached("INSERT INTO login_attempts (name, ip, success, time) VALUES (?1, ?2, ?3, ?4)")?
+                .execute(params![user_name, ip.map(|ip| ip.to_string()), success, time])?;
+            Ok(())
+        }).await
+    }
+
+    async fn account_login_failures(&self, user_name: &str, since: i64) -> Result<(u32, i64), Error> {
+        let user_name = name_key(user_name);
+        self.run(move |db| {
+            let failures = db.prepare_cached("SELECT COUNT(*), IFNULL(MAX(time), 0) FROM login_attempts
+                WHERE name = ?1 AND success = 0 AND time > ?2
+                AND time >= IFNULL((SELECT MAX(time) FROM login_attempts WHERE name = ?1 AND success = 1), 0)")?
+                .query_row(params![user_name, since], |row| Ok((row.get::<_, i64>(0)? as u32, row.get(1)?)))?;
+            Ok(failures)
+        }).await
+    }
+
+    async fn ip_login_failures(&self, ip: IpAddr, since: i64) -> Result<(u32, i64), Error> {
+        self.run(move |db| {
+            let failures = db.prepare_cached("SELECT COUNT(*), IFNULL(MAX(time), 0) FROM login_attempts
+                WHERE ip = ?1 AND success = 0 AND time > ?2")?
+                .query_row(params![ip.to_string(), since], |row| Ok((row.get::<_, i64>(0)? as u32, row.get(1)?)))?;
+            Ok(failures)
+        }).await
+    }
+
+    async fn write_game(&self, game: GameRecord) -> Result<(), Error> {
+        self.run(move |db| {
+            db.prepare_cached("INSERT INTO games
+                (uuid, date, size, white, black, notation, result, rated, white_rating, black_rating, time, increment, komi, event, round, stones, caps, no_swap) VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)")?
+                .execute(params![game.id.to_string(), game.date, game.size, game.white, game.black, game.notation,
+                    game.result, game.rated, game.white_rating, game.black_rating, game.time, game.increment,
+                    game.komi, game.event, game.round, game.stones, game.caps, game.no_swap])?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_game(&self, id: Uuid) -> Result<Option<GameRecord>, Error> {
+        self.run(move |db| {
+            let mut game_with_id = db.prepare_cached(&format!("SELECT {} FROM games WHERE uuid = ?1", GAME_COLUMNS))?;
+            Ok(game_with_id.query_row(params![id.to_string()], game_from_row).optional()?)
+        }).await
+    }
+
+    async fn list_games(&self, filter: GameFilter) -> Result<Vec<GameRecord>, Error> {
+        let player = filter.player.as_deref().map(name_key);
+        self.run(move |db| {
+            //only the conditions in use go into the query, so sqlite can pick the matching index
+            let mut conditions = Vec::new();
+            let mut values: Vec<&dyn ToSql> = Vec::new();
+            //nocase folds ascii letters only, just like the name key
+            if let Some(player) = &player {
+                conditions.push("(white = ? COLLATE NOCASE OR black = ? COLLATE NOCASE)");
+                values.push(player);
+                values.push(player);
+            }
+            if let Some(size) = &filter.size {
+                conditions.push("size = ?");
+                values.push(size);
+            }
+            if let Some(rated) = &filter.rated {
+                conditions.push("rated = ?");
+                values.push(rated);
+            }
+            if let Some(before) = &filter.before {
+                conditions.push("date < ?");
+                values.push(before);
+            }
+            if let Some(after) = &filter.after {
+                conditions.push("date > ?");
+                values.push(after);
+            }
+            values.push(&filter.limit);
+            values.push(&filter.offset);
+
+            let conditions = if conditions.is_empty() { String::new() } else { format!("WHERE {}", conditions.join(" AND ")) };
+            let mut matching = db.prepare_cached(&format!("SELECT {} FROM games {} ORDER BY date DESC, id DESC LIMIT ? OFFSET ?",
+                GAME_COLUMNS, conditions))?;
+            let games = matching.query_map(values, game_from_row)?;
+            Ok(games.collect::<rusqlite::Result<Vec<GameRecord>>>()?)
+        }).await
+    }
+
+    async fn player_games(&self, user_name: &str) -> Result<Vec<GameRecord>, Error> {
+        let user_name = String::from(user_name);
+        self.run(move |db| {
+            let mut games_with_player = db.prepare_cached(&format!("SELECT {} FROM games
+                WHERE white = ?1 OR black = ?1 ORDER BY date DESC", GAME_COLUMNS))?;
+            let games = games_with_player.query_map(params![user_name], game_from_row)?;
+            Ok(games.collect::<rusqlite::Result<Vec<GameRecord>>>()?)
+        }).await
+    }
+
+    async fn write_rating(&self, entry: RatingEntry) -> Result<(), Error> {
+        self.run(move |db| {
+            db.prepare_cached("INSERT INTO ratings (player_uuid, game_uuid, rating, time) VALUES (?1, ?2, ?3, ?4)")?
+                .execute(params![entry.player_id.to_string(), entry.game_id.map(|id| id.to_string()), entry.rating, entry.time])?;
+            Ok(())
+        }).await
+    }
+
+    async fn rating_history(&self, player_id: Uuid) -> Result<Vec<RatingEntry>, Error> {
+        self.run(move |db| {
+            let mut ratings_for_player = db.prepare_cached("SELECT game_uuid, rating, time FROM ratings
+                WHERE player_uuid = ?1 ORDER BY time, id")?;
+            let history = ratings_for_player.query_map(params![player_id.to_string()], |row| {
+                let game_id: Option<String> = row.get(0)?;
+                Ok(RatingEntry {
+                    player_id,
+                    game_id: game_id.and_then(|id| Uuid::from_str(&id).ok()),
+                    rating: row.get(1)?,
+                    time: row.get(2)?,
+                })
+            })?;
+            Ok(history.collect::<rusqlite::Result<Vec<RatingEntry>>>()?)
+        }).await
+    }
+}
+// Connection::open(path: P)
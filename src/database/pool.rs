@@ -0,0 +1,103 @@
+use rusqlite::Connection;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use crate::error::Error;
+
+//a fixed size set of sqlite connections, opened lazily and handed out to blocking tasks
+pub struct Pool {
+    path: String,
+    size: usize,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<Connection>,
+    open: usize,
+}
+
+impl Pool {
+    pub fn new(path: &str, size: usize) -> Self {
+        Pool {
+            path: String::from(path),
+            size: size.max(1),
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            returned: Condvar::new(),
+        }
+    }
+
+    //blocks until a connection is free, so only call this off the async runtime
+    pub fn get(&self) -> Result<PooledConnection<'_>, Error> {
+        let mut state = self.state.lock().map_err(|_| Error::System(String::from("database pool poisoned")))?;
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PooledConnection::new(self, connection));
+            }
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match Connection::open(&self.path) {
+                    Ok(connection) => Ok(PooledConnection::new(self, connection)),
+                    Err(err) => {
+                        self.forget();
+                        Err(err.into())
+                    }
+                };
+            }
+            state = self.returned.wait(state).map_err(|_| Error::System(String::from("database pool poisoned")))?;
+        }
+    }
+
+    fn put(&self, connection: Connection) {
+        if let Ok(mut state) = self.state.lock() {
+            state.idle.push(connection);
+            self.returned.notify_one();
+        }
+    }
+
+    fn forget(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.open -= 1;
+            self.returned.notify_one();
+        }
+    }
+}
+
+pub struct PooledConnection<'a> {
+    pool: &'a Pool,
+    connection: Option<Connection>,
+}
+
+impl<'a> PooledConnection<'a> {
+    fn new(pool: &'a Pool, connection: Connection) -> Self {
+        PooledConnection {
+            pool,
+            connection: Some(connection),
+        }
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().expect("connection is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection.as_mut().expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.put(connection);
+        }
+    }
+}
@@ -1,47 +1,112 @@
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast, watch, RwLock, Mutex};
 use std::time::Duration;
 use crate::model::user::User;
 use crate::tak::player::Player;
 use crate::database::Database;
+use crate::client::Client;
+use crate::config::{Config, StorageKind};
+use crate::storage::{Storage, MemoryStorage};
+use std::sync::Arc;
+use crate::rate_limit::{RateLimiter, InputKind};
+use crate::login_guard::LoginGuard;
+use crate::clock::unix_time;
+use crate::names::{GUEST_NAME_REGEX, MAX_NAME_LENGTH, NameProblem, name_key, validate_new_name};
+use crate::session::SessionTokens;
+use crate::metrics::{METRICS, Gauges};
+use crate::auth::{AuthHook, AllowAll};
 use uuid::Uuid;
-use log::{info, error};
+use tracing::{info, info_span, warn, error, field, Instrument};
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use futures::StreamExt;
 use tokio::sync::mpsc::UnboundedReceiver;
 use crate::proto::{InputParcel, Input, RegisterInput, OutputError, Output, OutputParcel,
-     PostInput, JoinedOutput, MessageOutput, SignInInput};
+     PostInput, JoinedOutput, MessageOutput, SignInInput, SignInWithTokenInput, ChangePasswordInput, ResetPasswordInput,
+     SessionTokenOutput, PlayerInfoInput, PlayerInfoOutput};
+use bot::Bot;
+use games::{Game, Seek};
 use regex::Regex;
 use tokio::time;
+use tokio::task;
 
 pub struct Hub {
     output_sender: broadcast::Sender<OutputParcel>,
     players: tokio::sync::RwLock<HashMap<Uuid, Player>>,
-    database: Database,
+    clients: RwLock<HashMap<Uuid, Client>>,
+    rate_limiter: Mutex<RateLimiter>,
+    max_connections_per_ip: usize,
+    login_guard: LoginGuard,
+    sessions: SessionTokens,
+    next_guest: AtomicU64,
+    storage: Arc<dyn Storage>,
+    auth_hook: Arc<dyn AuthHook>,
+    closing: (watch::Sender<bool>, watch::Receiver<bool>),
+    running: AtomicBool,
+    //keyed by the seeking client
+    seeks: RwLock<HashMap<Uuid, Seek>>,
+    games: RwLock<HashMap<Uuid, Game>>,
+    bot: Option<Bot>,
+}
+
+//clears the running flag however `Hub::run` ends, including by panicking or being dropped
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
 }
 
 const OUTPUT_CHANNEL_SIZE: usize = 16;
 const MAX_MESSAGE_BODY_LENGTH: usize = 256;
+const MAX_GUEST_MESSAGE_BODY_LENGTH: usize = 64;
 lazy_static! {
-    static ref USER_NAME_REGEX: Regex = Regex::new("[A-Za-z\\s]{4,24}").unwrap();
-    static ref GUEST_NAME_REGEX: Regex = Regex::new(r"Guest\d+").unwrap();
     static ref VALID_EMAIL_REGEX: Regex = Regex::new(r"^[\w!#$%&’*+/=?`{|}~^-]+(?:\.[\w!#$%&’*+/=?`{|}~^-]+)*@(?:[a-zA-Z0-9-]+\.)+[a-zA-Z]{2,6}$").unwrap();
 }
 
 impl Hub {
-    pub fn new() -> Self{
+    pub fn new(config: &Config) -> Self{
+        let storage: Arc<dyn Storage> = match config.storage {
+            StorageKind::Sqlite => Arc::new(Database::open(&config.database_path, config.database_connections)),
+            StorageKind::Memory => Arc::new(MemoryStorage::new()),
+        };
+        Self::with_storage(config, storage)
+    }
+
+    pub fn with_storage(config: &Config, storage: Arc<dyn Storage>) -> Self{
         let (output_sender, _) = broadcast::channel(OUTPUT_CHANNEL_SIZE);
         Hub{
             output_sender,
             players: Default::default(),
-            database: Database::new(),
+            clients: Default::default(),
+            rate_limiter: Mutex::new(RateLimiter::new(config.rate_limits.clone())),
+            max_connections_per_ip: config.max_connections_per_ip,
+            login_guard: LoginGuard::new(config.login_protection.clone()),
+            sessions: SessionTokens::new(config.token_secret.as_deref(), config.token_lifetime),
+            next_guest: AtomicU64::new(1),
+            storage,
+            auth_hook: Arc::new(AllowAll),
+            closing: watch::channel(false),
+            running: AtomicBool::new(false),
+            seeks: Default::default(),
+            games: Default::default(),
+            bot: config.bot.clone().map(Bot::new),
         }
     }
 
+    pub fn with_auth_hook(mut self, auth_hook: Arc<dyn AuthHook>) -> Self{
+        self.auth_hook = auth_hook;
+        self
+    }
+
     pub fn subscribe(&self) -> broadcast::Receiver<OutputParcel> {
         self.output_sender.subscribe()
     }
 
     pub async fn run(&self, receiver: UnboundedReceiver<InputParcel>){
+        self.running.store(true, Ordering::SeqCst);
+        let _running = RunningGuard(&self.running);
         // receiver.for_each(|input|{})
 
         let ticking_alive = self.tick_alive();
@@ -49,16 +114,117 @@ impl Hub {
         tokio::select!{
             // _ = ticking_alive => {},
             _ = processing => {},
+            _ = self.tick_clocks() => {},
+            _ = self.run_bot() => {},
         }
         //TODO is this right?
     }
 
+    //whether the input loop is alive, a hub whose loop died leaves connections open but never answers them
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    pub fn storage_ready(&self) -> bool {
+        self.storage.is_setup()
+    }
+
+    pub async fn is_signed_in(&self, client_id: Uuid) -> bool {
+        self.players.read().await.contains_key(&client_id)
+    }
+
+    //signed in players and guests, for embedding applications
+    pub async fn connected_players(&self) -> Vec<(Uuid, Player)> {
+        self.players.read().await.iter().map(|(client_id, player)| (*client_id, player.clone())).collect()
+    }
+
+    pub async fn gauges(&self) -> Gauges {
+        let players = self.players.read().await;
+        let guests = players.values().filter(|player| player.is_guest).count();
+        Gauges {
+            clients: self.clients.read().await.len(),
+            players: players.len() - guests,
+            guests,
+            games: self.games.read().await.len(),
+            seeks: self.seeks.read().await.len(),
+        }
+    }
+
+    //asks every connection to close, the server stops accepting new ones separately
+    pub fn close(&self){
+        if self.closing.0.broadcast(true).is_err() {
+            warn!("Closing a hub nobody is listening to");
+        }
+    }
+
+    //resolves once `close` has been called
+    pub async fn closed(&self){
+        let mut closing = self.closing.1.clone();
+        while let Some(closed) = closing.recv().await {
+            if closed {
+                return;
+            }
+        }
+    }
+
+    //registers a new connection, returns false if its ip already has too many open connections
+    pub async fn on_connect(&self, client: Client) -> bool {
+        let mut clients = self.clients.write().await;
+        if let Some(ip) = client.ip {
+            let open = clients.values().filter(|other| other.ip == Some(ip)).count();
+            if open >= self.max_connections_per_ip {
+                warn!(%ip, open, "Refusing connection, too many open from this ip");
+                return false;
+            }
+        }
+        clients.insert(client.id, client);
+        true
+    }
+
+    //every event logged while handling an input carries the client, the input type, the player if signed in and the game it is about
     async fn process(&self, input_parcel: InputParcel){
+        let span = info_span!("input", client_id = %input_parcel.client_id, input = input_parcel.input.name(),
+            player = field::Empty, game_id = field::Empty);
+        if let Some(player) = self.players.read().await.get(&input_parcel.client_id) {
+            span.record("player", player.name.as_str());
+        }
+        //accepting a seek records the game once it has started
+        if let Some(game_id) = input_parcel.input.game_id() {
+            span.record("game_id", field::display(game_id));
+        }
+        METRICS.input(input_parcel.input.name());
+        self.dispatch(input_parcel).instrument(span).await
+    }
+
+    async fn dispatch(&self, input_parcel: InputParcel){
+        let ip = self.client_ip(input_parcel.client_id).await;
+        let is_guest = self.players.read().await.get(&input_parcel.client_id).is_some_and(|player| player.is_guest);
+        let kind = InputKind::of(&input_parcel.input, is_guest);
+        if !self.rate_limiter.lock().await.check(input_parcel.client_id, ip, kind) {
+            warn!(kind = ?kind, "Rate limited");
+            self.send_error(input_parcel.client_id, OutputError::RateLimited);
+            return;
+        }
+
         match input_parcel.input{
             Input::Register(input) => self.process_register(input_parcel.client_id, input).await,
             Input::SignIn(input) => self.process_sign_in(input_parcel.client_id, input).await,
             Input::Post(input) => self.process_post(input_parcel.client_id, input).await,
-            _ => unreachable!()
+            Input::SignInWithToken(input) => self.process_sign_in_with_token(input_parcel.client_id, input).await,
+            Input::ChangePassword(input) => self.process_change_password(input_parcel.client_id, input).await,
+            Input::ResetPassword(input) => self.process_reset_password(input_parcel.client_id, input).await,
+            Input::JoinAsGuest => self.process_join_as_guest(input_parcel.client_id).await,
+            Input::PlayerInfo(input) => self.process_player_info(input_parcel.client_id, input).await,
+            Input::Seek(input) => self.process_seek(input_parcel.client_id, input).await,
+            Input::CancelSeek => self.process_cancel_seek(input_parcel.client_id).await,
+            Input::ListSeeks => self.process_list_seeks(input_parcel.client_id).await,
+            Input::AcceptSeek(input) => self.process_accept_seek(input_parcel.client_id, input).await,
+            Input::Move(input) => self.process_move(input_parcel.client_id, input).await,
+            Input::Resign(input) => self.process_resign(input_parcel.client_id, input).await,
         }
     }
 
@@ -67,36 +233,84 @@ impl Hub {
         let password = input.password.as_str();
         let email = input.email.trim();
 
-        // Validate user name
-        if !USER_NAME_REGEX.is_match(user_name) {
-            self.send_error(client_id, OutputError::InvalidName);
+        //guests may register to upgrade their session, anyone else is already an account
+        if let Some(current) = self.players.read().await.get(&client_id) {
+            if !current.is_guest {
+                self.send_error(client_id, OutputError::AlreadySignedIn);
+                return;
+            }
+        }
+        //the guest's seek and game hold the guest player, they would go on under a name nobody has any more
+        if self.game_of(client_id).await.is_some() {
+            self.send_error(client_id, OutputError::AlreadyInGame);
+            return;
+        }
+        if self.seeks.read().await.contains_key(&client_id) {
+            self.send_error(client_id, OutputError::SeekOpen);
             return;
         }
 
+        // Validate user name, guest names and the bot's are reserved for the server to hand out
+        let reserved = if self.reserves_bot_name(user_name) { Err(NameProblem::Reserved) } else { Ok(()) };
+        match validate_new_name(user_name).and(reserved) {
+            Ok(()) => {},
+            Err(NameProblem::Invalid) => {
+                self.send_error(client_id, OutputError::InvalidName);
+                return;
+            },
+            Err(NameProblem::Reserved) => {
+                self.send_error(client_id, OutputError::ReservedName);
+                return;
+            }
+        }
+
         // Validate email
         if !VALID_EMAIL_REGEX.is_match(email) {
             self.send_error(client_id, OutputError::InvalidEmail);
             return;
         }
 
-        //check to see if the user name exists in database
-        if let Ok(_) = self.database.get_user(user_name) {
-            self.send_error(client_id, OutputError::NameTaken);
-            return;
+        //check to see if the user name, or one that looks just like it, exists in database
+        let taken = match self.storage.get_user(user_name).await {
+            Ok(Some(_)) => Ok(Some(OutputError::NameTaken)),
+            Ok(None) => self.storage.similar_name_exists(user_name).await
+                .map(|similar| if similar { Some(OutputError::SimilarNameTaken) } else { None }),
+            Err(err) => Err(err),
+        };
+        match taken {
+            Ok(None) => {},
+            Ok(Some(taken)) => {
+                self.send_error(client_id, taken);
+                return;
+            },
+            Err(err) => {
+                error!("Error reading player from database: {}", err);
+                self.send_error(client_id, OutputError::ServerError);
+                return;
+            }
         }
 
+        let key = name_key(user_name);
         if self.players
             .read()
             .await
             .values()
-            .any(|user: &Player| user.name == user_name){
+            .any(|user: &Player| name_key(&user.name) == key){
                 self.send_error(client_id, OutputError::NameTaken);
                 return;
         }
 
+        let ip = self.client_ip(client_id).await;
+        if !self.auth_hook.allow_register(user_name, email, ip).await {
+            self.send_error(client_id, OutputError::NotAllowed);
+            return;
+        }
+
         //Validate the password
-        let player = Player::new(user_name, password, email, client_id, true);
-        match self.database.write_player(player.clone()) {
+        let mut player = Player::new(user_name, password, email, client_id, false);
+        player.joined = unix_time();
+        player.last_seen = player.joined;
+        match self.storage.write_player(player.clone()).await {
             Ok(written) => {
                 if !written {
                     self.send_error(client_id, OutputError::FailedWritingPlayer);
@@ -110,14 +324,55 @@ impl Hub {
             }
         }
 
-        //add them to the list of users
+        //add them to the list of users, replacing the guest they may have been playing as
         // let user = User::new(client_id, user_name);
+        let player_id = player.id;
         self.players.write().await.insert(client_id, player);
+        info!(player = user_name, "Registered and signed in");
 
-        self.send_targeted(client_id, Output::Joined(JoinedOutput::new(true)));
+        self.send_targeted(client_id, Output::Joined(JoinedOutput::new(true, user_name, false)));
+        self.issue_session_token(client_id, player_id).await;
 
     }
 
+    async fn process_join_as_guest(&self, client_id: Uuid){
+        let name = {
+            let players = self.players.read().await;
+            if players.contains_key(&client_id) {
+                self.send_error(client_id, OutputError::AlreadySignedIn);
+                return;
+            }
+
+            //registered names can never look like guest names, so only online guests can collide
+            loop {
+                let name = format!("Guest{}", self.next_guest.fetch_add(1, Ordering::Relaxed));
+                if !players.values().any(|player| player.name == name) {
+                    break name;
+                }
+            }
+        };
+
+        //the hook may look at who is online, so it runs without the players lock
+        let guest = Player::new(&name, "", "", client_id, true);
+        if !self.auth_hook.allow_sign_in(&guest, self.client_ip(client_id).await).await {
+            self.send_error(client_id, OutputError::NotAllowed);
+            return;
+        }
+
+        let mut players = self.players.write().await;
+        if players.contains_key(&client_id) {
+            self.send_error(client_id, OutputError::AlreadySignedIn);
+            return;
+        }
+        if players.values().any(|player| player.name == name) {
+            self.send_error(client_id, OutputError::NameTaken);
+            return;
+        }
+        players.insert(client_id, guest);
+        info!(player = %name, "Joined as guest");
+        self.send_targeted(client_id, Output::Joined(JoinedOutput::new(true, &name, true)));
+    }
+
     async fn process_post(&self, client_id: Uuid, input: PostInput){
 
         let user = if let Some(user) = self.players.read().await.get(&client_id){
@@ -127,7 +382,8 @@ impl Hub {
             return
         };
 
-        if input.body.is_empty() || input.body.len() > MAX_MESSAGE_BODY_LENGTH {
+        let max_length = if user.is_guest { MAX_GUEST_MESSAGE_BODY_LENGTH } else { MAX_MESSAGE_BODY_LENGTH };
+        if input.body.is_empty() || input.body.len() > max_length {
             self.send_error(client_id, OutputError::InvalidMessageBody);
             return
         };
@@ -140,34 +396,83 @@ impl Hub {
 
     }
 
+    async fn process_player_info(&self, client_id: Uuid, input: PlayerInfoInput){
+        let name = input.name.trim();
+
+        //guests only exist while they are online
+        let key = name_key(name);
+        let online = self.players.read().await.values().find(|player| name_key(&player.name) == key).cloned();
+        let player = match online {
+            Some(player) if player.is_guest => player,
+            _ => match self.storage.get_user(name).await {
+                Ok(Some(player)) => player,
+                Ok(None) => {
+                    self.send_error(client_id, OutputError::PlayerNotFound);
+                    return;
+                },
+                Err(err) => {
+                    error!("Error reading player from database: {}", err);
+                    self.send_error(client_id, OutputError::ServerError);
+                    return;
+                }
+            }
+        };
+
+        let online = self.players.read().await.values().any(|other| other.id == player.id);
+        let info = PlayerInfoOutput::new(&player, online, self.current_games(player.id).await);
+        self.send_targeted(client_id, Output::PlayerInfo(info));
+    }
+
     async fn process_sign_in(&self, client_id: Uuid, input: SignInInput){
         let user_name = input.name.trim();
         let password = input.password;
         
-        // Validate user name
-        if !USER_NAME_REGEX.is_match(user_name) {
+        // Validate user name, guests join through joinAsGuest instead
+        //imported accounts predate the registration rules, so only the length is checked here
+        if user_name.is_empty() || user_name.len() > MAX_NAME_LENGTH || GUEST_NAME_REGEX.is_match(user_name) {
             self.send_error(client_id, OutputError::InvalidName);
             return;
         }
 
-        //if the user is a guest, skip the rest of the validation.
-        if GUEST_NAME_REGEX.is_match(user_name) {
-            let guest = Player::new(user_name, "", "", client_id, true);
-            self.players.write().await.insert(client_id, guest);
-            self.send_targeted(client_id, Output::Joined(JoinedOutput::new(true)));
+        if self.players.read().await.contains_key(&client_id) {
+            self.send_error(client_id, OutputError::AlreadySignedIn);
             return;
         }
 
-        let mut player = if let Ok(player) = self.database.get_user(user_name){
-            info!("Found player {:?}", player);
-            player
-        } else {
-            self.send_error(client_id, OutputError::PlayerNotFound);
-            return;
+        let ip = self.client_ip(client_id).await;
+        match self.login_guard.retry_after(self.storage.as_ref(), user_name, ip).await {
+            Ok(Some(retry_after)) => {
+                self.send_error(client_id, OutputError::SignInLocked { retry_after });
+                return;
+            },
+            Ok(None) => {},
+            Err(err) => error!("Error checking sign in attempts: {}", err),
+        }
+
+        let player = match self.storage.get_user(user_name).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                self.record_sign_in(user_name, ip, false).await;
+                self.send_error(client_id, OutputError::PlayerNotFound);
+                return;
+            },
+            Err(err) => {
+                error!("Error reading player from database: {}", err);
+                self.send_error(client_id, OutputError::ServerError);
+                return;
+            }
         };
 
-        if let Ok(is_pass_valid) = Player::verify_password(password, &player.password) {
+        //an account waiting on a reset has no usable password, it fails just like a wrong one so nobody can tell which accounts are flagged
+        if player.password_reset {
+            self.record_sign_in(user_name, ip, false).await;
+            self.send_error(client_id, OutputError::InvalidPassword);
+            return;
+        }
+
+        if let Ok(is_pass_valid) = Self::verify_password(password, &player.password).await {
             if !is_pass_valid {
+                self.record_sign_in(user_name, ip, false).await;
                 self.send_error(client_id, OutputError::InvalidPassword);
                 return;
             }
@@ -176,44 +481,221 @@ impl Hub {
             return;
         }
 
+        let player_id = player.id;
+        if self.join(client_id, player).await {
+            self.record_sign_in(user_name, ip, true).await;
+            self.issue_session_token(client_id, player_id).await;
+        }
+    }
+
+    async fn process_sign_in_with_token(&self, client_id: Uuid, input: SignInWithTokenInput){
+        if self.players.read().await.contains_key(&client_id) {
+            self.send_error(client_id, OutputError::AlreadySignedIn);
+            return;
+        }
+
+        let token_hash = if let Some(token_hash) = self.sessions.verify(&input.token, unix_time()) {
+            token_hash
+        } else {
+            self.send_error(client_id, OutputError::InvalidToken);
+            return;
+        };
+
+        let player = match self.storage.get_session_user(&token_hash, unix_time()).await {
+            Ok(Some(player)) => player,
+            Ok(None) => {
+                info!("Rejected expired or revoked session token");
+                self.send_error(client_id, OutputError::InvalidToken);
+                return;
+            },
+            Err(err) => {
+                error!("Error reading session from database: {}", err);
+                self.send_error(client_id, OutputError::ServerError);
+                return;
+            }
+        };
+
+        self.join(client_id, player).await;
+    }
+
+    async fn process_change_password(&self, client_id: Uuid, input: ChangePasswordInput){
+        let player = match self.players.read().await.get(&client_id) {
+            Some(player) if !player.is_guest => player.clone(),
+            _ => {
+                self.send_error(client_id, OutputError::NotJoined);
+                return;
+            }
+        };
+
+        //the copy held in `players` may carry the plain password from registration, so check against the database
+        let stored = match self.storage.get_user_by_id(player.id).await {
+            Ok(Some(stored)) => stored,
+            Ok(None) => {
+                self.send_error(client_id, OutputError::PlayerNotFound);
+                return;
+            },
+            Err(err) => {
+                error!("Error reading player from database: {}", err);
+                self.send_error(client_id, OutputError::ServerError);
+                return;
+            }
+        };
+
+        //guessing the old password here is as good as guessing it at sign in, so the same lockout applies
+        let ip = self.client_ip(client_id).await;
+        match self.login_guard.retry_after(self.storage.as_ref(), &stored.name, ip).await {
+            Ok(Some(retry_after)) => {
+                self.send_error(client_id, OutputError::SignInLocked { retry_after });
+                return;
+            },
+            Ok(None) => {},
+            Err(err) => error!("Error checking sign in attempts: {}", err),
+        }
+
+        match Self::verify_password(input.password, &stored.password).await {
+            Ok(true) => self.record_sign_in(&stored.name, ip, true).await,
+            Ok(false) => {
+                self.record_sign_in(&stored.name, ip, false).await;
+                self.send_error(client_id, OutputError::InvalidPassword);
+                return;
+            },
+            Err(_) => {
+                self.send_error(client_id, OutputError::UnableToVerifyPassword);
+                return;
+            }
+        }
+
+        if let Err(err) = self.storage.update_password(player.id, input.new_password).await {
+            error!("Error updating password: {}", err);
+            self.send_error(client_id, OutputError::FailedWritingPlayer);
+            return;
+        }
+
+        //every token issued so far was handed out under the old password
+        if let Err(err) = self.storage.revoke_sessions(player.id).await {
+            error!("Error revoking sessions: {}", err);
+        }
+
+        self.send_targeted(client_id, Output::PasswordChanged);
+        self.issue_session_token(client_id, player.id).await;
+    }
+
+    //marks a verified player as signed in on this client, returns false if they could not join
+    async fn join(&self, client_id: Uuid, mut player: Player) -> bool {
         if let Some(_) =  player.get_client() {
             self.send_error(client_id, OutputError::LoginOnOtherClient);
-            return;
+            return false;
         }
 
-        if self.players.read().await.values().any(|player: &Player| {
-            player.name == user_name
+        if self.players.read().await.values().any(|other: &Player| {
+            name_key(&other.name) == name_key(&player.name)
         }) {
             self.send_error(client_id, OutputError::NameTaken);
-            return;
+            return false;
+        }
+
+        if !self.auth_hook.allow_sign_in(&player, self.client_ip(client_id).await).await {
+            self.send_error(client_id, OutputError::NotAllowed);
+            return false;
         }
 
+        let joined = JoinedOutput::new(true, &player.name, false);
+        self.touch_last_seen(&player).await;
         player.set_client(client_id); //set the player's client id
+        info!(player = %player.name, "Signed in");
         self.players.write().await.insert(client_id, player);
 
-        self.send_targeted(client_id, Output::Joined(JoinedOutput::new(true)));
+        self.send_targeted(client_id, Output::Joined(joined));
+        true
+    }
+
+    async fn process_reset_password(&self, client_id: Uuid, input: ResetPasswordInput){
+        let token_hash = SessionTokens::hash(&input.token);
+        let player_id = match self.storage.take_password_reset(&token_hash, unix_time()).await {
+            Ok(Some(player_id)) => player_id,
+            Ok(None) => {
+                self.send_error(client_id, OutputError::InvalidToken);
+                return;
+            },
+            Err(err) => {
+                error!("Error reading password reset from database: {}", err);
+                self.send_error(client_id, OutputError::ServerError);
+                return;
+            }
+        };
 
+        //clears the reset flag too
+        if let Err(err) = self.storage.update_password(player_id, input.new_password).await {
+            error!("Error updating password: {}", err);
+            self.send_error(client_id, OutputError::FailedWritingPlayer);
+            return;
+        }
+        if let Err(err) = self.storage.revoke_sessions(player_id).await {
+            error!("Error revoking sessions: {}", err);
+        }
+        info!(player = %player_id, "Password reset");
+        self.send_targeted(client_id, Output::PasswordChanged);
+    }
+
+    async fn touch_last_seen(&self, player: &Player){
+        if player.is_guest {
+            return;
+        }
+        if let Err(err) = self.storage.update_last_seen(player.id, unix_time()).await {
+            error!("Error updating last seen time: {}", err);
+        }
+    }
+
+    async fn issue_session_token(&self, client_id: Uuid, player_id: Uuid){
+        let issued = self.sessions.issue(unix_time());
+        if let Err(err) = self.storage.write_session(&issued.hash, player_id, issued.expires).await {
+            error!("Error writing session to database: {}", err);
+            return;
+        }
+        self.send_targeted(client_id, Output::SessionToken(SessionTokenOutput::new(issued.token, issued.expires)));
+    }
+
+    async fn record_sign_in(&self, user_name: &str, ip: Option<IpAddr>, success: bool){
+        if let Err(err) = self.login_guard.record(self.storage.as_ref(), user_name, ip, success).await {
+            error!("Error recording sign in attempt: {}", err);
+        }
+    }
+
+    //bcrypt is deliberately slow, keep it off the async runtime
+    async fn verify_password(password: String, hash: &str) -> Result<bool, ()> {
+        let hash = String::from(hash);
+        match task::spawn_blocking(move || Player::verify_password(password, &hash)).await {
+            Ok(Ok(is_valid)) => Ok(is_valid),
+            _ => Err(()),
+        }
+    }
+
+    async fn client_ip(&self, client_id: Uuid) -> Option<IpAddr> {
+        self.clients.read().await.get(&client_id).and_then(|client| client.ip)
     }
 
     fn send_error(&self, client_id: Uuid, error: OutputError){
+        if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(error) {
+            if let Some(serde_json::Value::String(code)) = fields.get("code") {
+                METRICS.error(code);
+            }
+        }
         self.send_targeted(client_id, Output::Error(error));
     }
 
     fn send_targeted(&self, client_id: Uuid, output: Output){
+        METRICS.output(output.name());
         if self.output_sender.receiver_count() > 0 {
             self.output_sender.send(OutputParcel::new(client_id, output)).unwrap();
         }
     }
 
-    async fn send(&self, output: Output) {
-        if self.output_sender.receiver_count() == 0 {
-            return;
+    //one parcel for everyone, each connection's writer picks it up once its player has signed in
+    fn send(&self, output: Output) {
+        METRICS.output(output.name());
+        if self.output_sender.receiver_count() > 0 {
+            self.output_sender.send(OutputParcel::to_players(output)).unwrap();
         }
-        self.players.read().await.keys().for_each(|client_id| {
-            self.output_sender
-                .send(OutputParcel::new(*client_id, output.clone()))
-                .unwrap();
-        });
     }
 
     fn validate_password(&self, user_name: &str, password: &str) -> bool{
@@ -226,21 +708,32 @@ impl Hub {
     }
 
     pub async fn on_disconnect(&self, client_id: Uuid){
-        if self.players.write().await.remove(&client_id).is_some() {
-            //TODO do something when the user is removed?
+        self.clients.write().await.remove(&client_id);
+        self.rate_limiter.lock().await.forget_client(client_id);
+        self.leave_games(client_id).await;
+        let removed = self.players.write().await.remove(&client_id);
+        if let Some(player) = removed {
+            info!(player = %player.name, "Signed out");
+            self.touch_last_seen(&player).await;
         }
     }
 
     async fn tick_alive(&self){
         loop{
             time::delay_for(Duration::from_secs(5)).await;
-            self.send(Output::Alive).await;
+            self.send(Output::Alive);
         }
     }
 }
 
 impl Default for Hub {
     fn default() -> Self {
-        Self::new()
+        Self::new(&Config::default())
     }
-}
\ No newline at end of file
+}
+
+mod bot;
+mod games;
+mod tei;
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,211 @@
+use super::*;
+use futures::future;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use crate::config::BotConfig;
+use crate::names::name_skeleton;
+use crate::proto::{MoveInput, ResignInput, SeekColor, SeekInput};
+use crate::tak::board::{Board, Color};
+use crate::tak::moves::Move;
+use crate::tak::search;
+use super::tei::{Clocks, Engine, Position};
+
+//a player hosted by the hub itself, with no connection behind it
+pub(crate) struct Bot {
+    pub config: BotConfig,
+    pub client_id: Uuid,
+    //games where it is the bot's turn
+    turns: mpsc::UnboundedSender<Uuid>,
+    waiting: Mutex<Option<UnboundedReceiver<Uuid>>>,
+    //running while an engine is configured, restarted after it fails
+    engine: Mutex<Option<Engine>>,
+}
+
+impl Bot {
+    pub fn new(config: BotConfig) -> Self {
+        let (turns, waiting) = mpsc::unbounded_channel();
+        Bot {
+            config,
+            client_id: Uuid::new_v4(),
+            turns,
+            waiting: Mutex::new(Some(waiting)),
+            engine: Mutex::new(None),
+        }
+    }
+}
+
+impl Hub {
+    //signs the bot in and plays its games for as long as the hub runs, never resolves when there is no bot
+    pub(super) async fn run_bot(&self){
+        let bot = match &self.bot {
+            Some(bot) => bot,
+            None => return future::pending().await,
+        };
+        let mut turns = match bot.waiting.lock().await.take() {
+            Some(turns) => turns,
+            None => return future::pending().await,
+        };
+
+        //an engine that will not start would leave the bot's games hanging, so it stays away
+        if let Some(config) = &bot.config.engine {
+            match Engine::start(config).await {
+                Ok(engine) => {
+                    info!(command = %config.command, engine = ?engine.name, "Engine started");
+                    *bot.engine.lock().await = Some(engine);
+                },
+                Err(err) => {
+                    error!(command = %config.command, "Engine failed to start: {}", err);
+                    return future::pending().await;
+                }
+            }
+        }
+
+        //stored games go by name, an account already holding it would be credited with the bot's games
+        let taken = match self.storage.get_user(&bot.config.name).await {
+            Ok(Some(_)) => Ok(true),
+            Ok(None) => self.storage.similar_name_exists(&bot.config.name).await,
+            Err(err) => Err(err),
+        };
+        match taken {
+            Ok(false) => {},
+            Ok(true) => {
+                error!(player = %bot.config.name, "An account already has the bot's name, the bot stays away");
+                return future::pending().await;
+            },
+            Err(err) => {
+                error!("Error reading player from database: {}", err);
+                return future::pending().await;
+            }
+        }
+
+        let player = Player::bot(&bot.config.name, bot.client_id);
+        self.players.write().await.insert(bot.client_id, player);
+        info!(player = %bot.config.name, depth = bot.config.depth, think_time = bot.config.think_time, "Bot joined");
+        self.post_bot_seek().await;
+
+        while let Some(game_id) = turns.recv().await {
+            let (board, position) = match self.games.read().await.get(&game_id) {
+                Some(game) if game.seat(game.board.to_move()).client_id == bot.client_id => {
+                    (game.board.clone(), bot_position(game, &bot.config))
+                },
+                _ => continue,
+            };
+            let span = info_span!("bot", game = %game_id);
+            let chosen = self.bot_move(bot, board.clone(), position).instrument(span.clone()).await;
+            //nobody would ever answer an error sent to the bot, so a game it cannot go on with would hang
+            match chosen.filter(|mv| board.check(mv).is_ok()) {
+                Some(mv) => {
+                    let input = MoveInput { game_id, ply: mv.to_string() };
+                    self.process_move(bot.client_id, input).instrument(span).await;
+                },
+                None => {
+                    warn!(game = %game_id, "Bot resigned, it had no legal move to play");
+                    self.process_resign(bot.client_id, ResignInput { game_id }).instrument(span).await;
+                },
+            }
+        }
+    }
+
+    //none when the bot could not come up with a move
+    async fn bot_move(&self, bot: &Bot, board: Board, position: Position) -> Option<Move> {
+        if let Some(config) = &bot.config.engine {
+            let mut engine = bot.engine.lock().await;
+            if engine.is_none() {
+                *engine = Engine::start(config).await.map_err(|err| error!("Engine failed to restart: {}", err)).ok();
+            }
+            if let Some(running) = engine.as_mut() {
+                match running.best_move(&position).await {
+                    Ok(mv) if board.check(&mv).is_ok() => return Some(mv),
+                    Ok(mv) => error!(ply = %mv, "Engine chose an illegal move"),
+                    Err(err) => error!("Engine failed to move: {}", err),
+                }
+                //dropping the engine kills it, the next turn starts a fresh one
+                *engine = None;
+            }
+            //the built in search stands in so the game goes on
+            warn!("Searching in place of the engine");
+        }
+
+        let depth = bot.config.depth;
+        let budget = position.think_time;
+        //searching is cpu bound, keep it off the async runtime
+        match task::spawn_blocking(move || search::best_move(&board, depth, budget)).await {
+            Ok(Some(mv)) => Some(mv),
+            Ok(None) => {
+                warn!("Bot found no move to play");
+                None
+            },
+            Err(err) => {
+                error!("Bot search failed: {}", err);
+                None
+            },
+        }
+    }
+
+    pub(super) async fn post_bot_seek(&self){
+        let bot = match &self.bot {
+            Some(bot) => bot,
+            None => return,
+        };
+        let seek = SeekInput {
+            size: bot.config.size,
+            time: bot.config.time,
+            increment: bot.config.increment,
+            komi: bot.config.komi,
+            color: SeekColor::Any,
+            rated: false,
+            stones: None,
+            caps: None,
+            no_swap: false,
+        };
+        self.process_seek(bot.client_id, seek).await;
+        if !self.seeks.read().await.contains_key(&bot.client_id) {
+            warn!(player = %bot.config.name, "The bot's seek was refused, check its configuration");
+        }
+    }
+
+    //no account may take the bot's name, or one that looks just like it
+    pub(super) fn reserves_bot_name(&self, name: &str) -> bool {
+        self.bot.as_ref().is_some_and(|bot| name_skeleton(&bot.config.name) == name_skeleton(name))
+    }
+
+    pub(super) fn is_bot(&self, client_id: Uuid) -> bool {
+        self.bot.as_ref().is_some_and(|bot| bot.client_id == client_id)
+    }
+
+    //wakes the bot when `next` is the bot
+    pub(super) fn notify_bot(&self, game_id: Uuid, next: Uuid){
+        if let Some(bot) = &self.bot {
+            if bot.client_id == next && bot.turns.send(game_id).is_err() {
+                warn!(game = %game_id, "Bot is not running");
+            }
+        }
+    }
+}
+
+//the game as an engine sees it, with the mover's clock running since their opponent moved
+fn bot_position(game: &Game, config: &BotConfig) -> Position {
+    let clocks = if game.time > 0 {
+        let elapsed = Instant::now().duration_since(game.last_move);
+        let mut clocks = Clocks {
+            white: game.white.clock,
+            black: game.black.clock,
+            increment: Duration::from_secs(game.increment as u64),
+        };
+        match game.board.to_move() {
+            Color::White => clocks.white = clocks.white.checked_sub(elapsed).unwrap_or_default(),
+            Color::Black => clocks.black = clocks.black.checked_sub(elapsed).unwrap_or_default(),
+        }
+        Some(clocks)
+    } else {
+        None
+    };
+    Position {
+        game_id: game.id,
+        size: game.board.size(),
+        half_komi: game.board.options().half_komi,
+        plies: game.plies.clone(),
+        clocks,
+        think_time: Duration::from_millis(config.think_time),
+    }
+}
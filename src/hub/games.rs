@@ -0,0 +1,456 @@
+use super::*;
+use std::time::Instant;
+use crate::model::game::{GameRecord, RatingEntry};
+use crate::proto::{SeekInput, SeekColor, AcceptSeekInput, MoveInput, ResignInput, SeekOutput, SeeksOutput,
+     SeekRemovedOutput, GameStartedOutput, MovedOutput, GameOverOutput};
+use crate::tak::board::{Board, Color, GameOptions, GameResult, MIN_SIZE, MAX_SIZE};
+use crate::tak::moves::Move;
+use crate::tak::rating;
+use tracing::Span;
+
+//four flats, more than any size needs to even out the first move
+const MAX_HALF_KOMI: u8 = 8;
+const MAX_TIME: u32 = 3 * 60 * 60;
+const MAX_INCREMENT: u32 = 5 * 60;
+//how often clocks are checked for players who ran out of time without moving
+const FLAG_INTERVAL: Duration = Duration::from_millis(100);
+
+//an open offer to play, at most one per client
+#[derive(Debug, Clone)]
+pub(crate) struct Seek {
+    pub id: Uuid,
+    pub client_id: Uuid,
+    pub player: Player,
+    pub options: GameOptions,
+    pub time: u32,
+    pub increment: u32,
+    pub color: SeekColor,
+    pub rated: bool,
+}
+
+impl Seek {
+    fn to_output(&self) -> SeekOutput {
+        SeekOutput {
+            id: self.id,
+            player: self.player.name.clone(),
+            size: self.options.size,
+            komi: self.options.half_komi,
+            time: self.time,
+            increment: self.increment,
+            color: self.color,
+            rated: self.rated,
+            stones: self.options.stones,
+            caps: self.options.caps,
+            no_swap: self.options.no_swap,
+        }
+    }
+}
+
+//a player in a game, as they were when it started
+#[derive(Debug, Clone)]
+pub(crate) struct Seat {
+    pub client_id: Uuid,
+    pub player: Player,
+    //time left on their clock
+    pub clock: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Game {
+    pub id: Uuid,
+    pub date: i64,
+    pub white: Seat,
+    pub black: Seat,
+    pub board: Board,
+    pub plies: Vec<String>,
+    pub time: u32,
+    pub increment: u32,
+    pub rated: bool,
+    pub last_move: Instant,
+}
+
+impl Game {
+    pub(super) fn seat(&self, color: Color) -> &Seat {
+        match color {
+            Color::White => &self.white,
+            Color::Black => &self.black,
+        }
+    }
+
+    fn seat_mut(&mut self, color: Color) -> &mut Seat {
+        match color {
+            Color::White => &mut self.white,
+            Color::Black => &mut self.black,
+        }
+    }
+
+    //whether the player to move has used up their clock, untimed games never flag
+    fn is_flagged(&self, now: Instant) -> bool {
+        self.time > 0 && self.seat(self.board.to_move()).clock <= now.duration_since(self.last_move)
+    }
+
+    fn color_of(&self, client_id: Uuid) -> Option<Color> {
+        if self.white.client_id == client_id {
+            Some(Color::White)
+        } else if self.black.client_id == client_id {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    fn has_player(&self, player_id: Uuid) -> bool {
+        self.white.player.id == player_id || self.black.player.id == player_id
+    }
+
+    fn to_record(&self, result: GameResult) -> GameRecord {
+        GameRecord {
+            id: self.id,
+            date: self.date,
+            size: self.board.size(),
+            white: self.white.player.name.clone(),
+            black: self.black.player.name.clone(),
+            notation: self.plies.join(" "),
+            result: String::from(result.to_ptn()),
+            rated: self.rated,
+            white_rating: self.white.player.stats.rating,
+            black_rating: self.black.player.stats.rating,
+            time: self.time,
+            increment: self.increment,
+            komi: self.board.options().komi(),
+            stones: self.board.options().stones,
+            caps: self.board.options().caps,
+            no_swap: self.board.options().no_swap,
+            event: None,
+            round: None,
+        }
+    }
+}
+
+impl Hub {
+    pub(super) async fn process_seek(&self, client_id: Uuid, input: SeekInput){
+        let player = if let Some(player) = self.players.read().await.get(&client_id) {
+            player.clone()
+        } else {
+            self.send_error(client_id, OutputError::NotJoined);
+            return;
+        };
+        if self.game_of(client_id).await.is_some() {
+            self.send_error(client_id, OutputError::AlreadyInGame);
+            return;
+        }
+
+        let options = GameOptions::standard(input.size)
+            .filter(|_| (MIN_SIZE..=MAX_SIZE).contains(&input.size))
+            .and_then(|options| options.with_pieces(input.stones.unwrap_or(options.stones), input.caps.unwrap_or(options.caps)));
+        let options = match options {
+            Some(options) => options,
+            None => {
+                self.send_error(client_id, OutputError::InvalidSeek);
+                return;
+            }
+        };
+        //custom counts must leave white something to play
+        if input.komi > MAX_HALF_KOMI || input.time > MAX_TIME || input.increment > MAX_INCREMENT
+            || (input.rated && !player.can_play_rated()) || !Board::new(options).has_legal_move() {
+            self.send_error(client_id, OutputError::InvalidSeek);
+            return;
+        }
+
+        let seek = Seek {
+            id: Uuid::new_v4(),
+            client_id,
+            player,
+            options: options.with_komi(input.komi).with_no_swap(input.no_swap),
+            time: input.time,
+            increment: input.increment,
+            color: input.color,
+            rated: input.rated,
+        };
+        let added = seek.to_output();
+        let replaced = self.seeks.write().await.insert(client_id, seek);
+        if let Some(replaced) = replaced {
+            self.send(Output::SeekRemoved(SeekRemovedOutput { id: replaced.id }));
+        }
+        info!(seek = %added.id, size = added.size, komi = added.komi, stones = added.stones, caps = added.caps, "Seek posted");
+        self.send(Output::SeekAdded(added));
+    }
+
+    pub(super) async fn process_cancel_seek(&self, client_id: Uuid){
+        if !self.remove_seek(client_id).await {
+            self.send_error(client_id, OutputError::SeekNotFound);
+        }
+    }
+
+    pub(super) async fn process_list_seeks(&self, client_id: Uuid){
+        let seeks = self.seeks.read().await.values().map(Seek::to_output).collect();
+        self.send_targeted(client_id, Output::Seeks(SeeksOutput { seeks }));
+    }
+
+    pub(super) async fn process_accept_seek(&self, client_id: Uuid, input: AcceptSeekInput){
+        let player = if let Some(player) = self.players.read().await.get(&client_id) {
+            player.clone()
+        } else {
+            self.send_error(client_id, OutputError::NotJoined);
+            return;
+        };
+        if self.game_of(client_id).await.is_some() {
+            self.send_error(client_id, OutputError::AlreadyInGame);
+            return;
+        }
+
+        let seek = {
+            let mut seeks = self.seeks.write().await;
+            let seeker = match seeks.values().find(|seek| seek.id == input.id) {
+                Some(seek) => seek.client_id,
+                None => {
+                    self.send_error(client_id, OutputError::SeekNotFound);
+                    return;
+                }
+            };
+            if seeker == client_id || (seeks[&seeker].rated && !player.can_play_rated()) {
+                self.send_error(client_id, OutputError::InvalidSeek);
+                return;
+            }
+            seeks.remove(&seeker).unwrap()
+        };
+        self.send(Output::SeekRemoved(SeekRemovedOutput { id: seek.id }));
+        self.remove_seek(client_id).await;
+
+        let seeker_is_white = match seek.color {
+            SeekColor::White => true,
+            SeekColor::Black => false,
+            SeekColor::Any => rand::random(),
+        };
+        let clock = Duration::from_secs(seek.time as u64);
+        let seeker = Seat { client_id: seek.client_id, player: seek.player, clock };
+        let accepter = Seat { client_id, player, clock };
+        let (white, black) = if seeker_is_white { (seeker, accepter) } else { (accepter, seeker) };
+        let game = Game {
+            id: Uuid::new_v4(),
+            date: unix_time(),
+            white,
+            black,
+            board: Board::new(seek.options),
+            plies: Vec::new(),
+            time: seek.time,
+            increment: seek.increment,
+            rated: seek.rated,
+            last_move: Instant::now(),
+        };
+
+        let started = GameStartedOutput {
+            game_id: game.id,
+            white: game.white.player.name.clone(),
+            black: game.black.player.name.clone(),
+            size: seek.options.size,
+            komi: seek.options.half_komi,
+            time: game.time,
+            increment: game.increment,
+            rated: game.rated,
+            stones: seek.options.stones,
+            caps: seek.options.caps,
+            no_swap: seek.options.no_swap,
+        };
+        Span::current().record("game_id", field::display(game.id));
+        info!(game = %game.id, white = %started.white, black = %started.black, "Game started");
+        self.send_targeted(game.white.client_id, Output::GameStarted(started.clone()));
+        self.send_targeted(game.black.client_id, Output::GameStarted(started));
+        let (game_id, first) = (game.id, game.white.client_id);
+        self.games.write().await.insert(game.id, game);
+        self.notify_bot(game_id, first);
+    }
+
+    pub(super) async fn process_move(&self, client_id: Uuid, input: MoveInput){
+        let mut games = self.games.write().await;
+        let game = match games.get_mut(&input.game_id) {
+            Some(game) if game.color_of(client_id).is_some() => game,
+            _ => {
+                self.send_error(client_id, OutputError::GameNotFound);
+                return;
+            }
+        };
+        let color = game.board.to_move();
+        if game.seat(color).client_id != client_id {
+            self.send_error(client_id, OutputError::NotYourTurn);
+            return;
+        }
+
+        //a player who ran out of time loses however good the move was
+        let now = Instant::now();
+        if game.is_flagged(now) {
+            let game = games.remove(&input.game_id).unwrap();
+            drop(games);
+            self.flag(game).await;
+            return;
+        }
+
+        let result = match input.ply.parse::<Move>().map_err(|_| ()).and_then(|mv| {
+            game.board.play(&mv).map(|result| (mv, result)).map_err(|_| ())
+        }) {
+            Ok((mv, result)) => {
+                game.plies.push(mv.to_string());
+                result
+            },
+            Err(()) => {
+                self.send_error(client_id, OutputError::IllegalMove);
+                return;
+            }
+        };
+        //only a move that was played is charged, a rejected one leaves the clock running as it was
+        if game.time > 0 {
+            let elapsed = now.duration_since(game.last_move);
+            let increment = Duration::from_secs(game.increment as u64);
+            let seat = game.seat_mut(color);
+            seat.clock = seat.clock - elapsed + increment;
+        }
+        game.last_move = now;
+
+        let moved = MovedOutput {
+            game_id: game.id,
+            ply: game.plies.last().cloned().unwrap_or_default(),
+            white_time: game.white.clock.as_millis() as u64,
+            black_time: game.black.clock.as_millis() as u64,
+            tak: game.board.is_tak(),
+        };
+        self.send_targeted(game.white.client_id, Output::Moved(moved.clone()));
+        self.send_targeted(game.black.client_id, Output::Moved(moved));
+
+        if let Some(result) = result {
+            let game = games.remove(&input.game_id).unwrap();
+            drop(games);
+            self.finish_game(game, result).await;
+        } else {
+            self.notify_bot(game.id, game.seat(game.board.to_move()).client_id);
+        }
+    }
+
+    pub(super) async fn process_resign(&self, client_id: Uuid, input: ResignInput){
+        let mut games = self.games.write().await;
+        let color = match games.get(&input.game_id).and_then(|game| game.color_of(client_id)) {
+            Some(color) => color,
+            None => {
+                self.send_error(client_id, OutputError::GameNotFound);
+                return;
+            }
+        };
+        let game = games.remove(&input.game_id).unwrap();
+        drop(games);
+        self.finish_game(game, GameResult::Default(color.opponent())).await;
+    }
+
+    //removes the client's seek and tells everyone, returns false if they had none
+    async fn remove_seek(&self, client_id: Uuid) -> bool {
+        let removed = self.seeks.write().await.remove(&client_id);
+        match removed {
+            Some(seek) => {
+                self.send(Output::SeekRemoved(SeekRemovedOutput { id: seek.id }));
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub(super) async fn game_of(&self, client_id: Uuid) -> Option<Uuid> {
+        self.games.read().await.values().find(|game| game.color_of(client_id).is_some()).map(|game| game.id)
+    }
+
+    pub(super) async fn current_games(&self, player_id: Uuid) -> Vec<Uuid> {
+        self.games.read().await.values().filter(|game| game.has_player(player_id)).map(|game| game.id).collect()
+    }
+
+    //a leaving client loses the game they were in and takes their seek with them
+    pub(super) async fn leave_games(&self, client_id: Uuid){
+        self.remove_seek(client_id).await;
+        if let Some(game_id) = self.game_of(client_id).await {
+            let removed = self.games.write().await.remove(&game_id);
+            if let Some(game) = removed {
+                let color = game.color_of(client_id).unwrap();
+                self.finish_game(game, GameResult::Default(color.opponent())).await;
+            }
+        }
+    }
+
+    //ends games whose player to move ran out of time, they would otherwise only lose on their next move
+    pub(super) async fn flag_idle_players(&self){
+        let now = Instant::now();
+        if !self.games.read().await.values().any(|game| game.is_flagged(now)) {
+            return;
+        }
+        let flagged: Vec<Game> = {
+            let mut games = self.games.write().await;
+            let ids: Vec<Uuid> = games.values().filter(|game| game.is_flagged(now)).map(|game| game.id).collect();
+            ids.iter().filter_map(|id| games.remove(id)).collect()
+        };
+        for game in flagged {
+            self.flag(game).await;
+        }
+    }
+
+    pub(super) async fn tick_clocks(&self){
+        let mut interval = time::interval(FLAG_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.flag_idle_players().await;
+        }
+    }
+
+    async fn flag(&self, mut game: Game){
+        let color = game.board.to_move();
+        game.seat_mut(color).clock = Duration::from_secs(0);
+        self.finish_game(game, GameResult::Default(color.opponent())).await;
+    }
+
+    //tells both players, stores the game and rates it
+    async fn finish_game(&self, game: Game, result: GameResult){
+        info!(game = %game.id, result = result.to_ptn(), "Game over");
+        let over = GameOverOutput { game_id: game.id, result: String::from(result.to_ptn()) };
+        self.send_targeted(game.white.client_id, Output::GameOver(over.clone()));
+        self.send_targeted(game.black.client_id, Output::GameOver(over));
+
+        //the bot seeks again as soon as a game ends
+        if self.is_bot(game.white.client_id) || self.is_bot(game.black.client_id) {
+            self.post_bot_seek().await;
+        }
+
+        let record = game.to_record(result);
+        if let Err(err) = self.storage.write_game(record.clone()).await {
+            error!("Error writing game to database: {}", err);
+            return;
+        }
+        if let (true, Some(white_score)) = (record.rated, record.white_score()) {
+            self.rate(&game.white, white_score, record.black_rating, &record).await;
+            self.rate(&game.black, 1.0 - white_score, record.white_rating, &record).await;
+        }
+    }
+
+    async fn rate(&self, seat: &Seat, score: f64, opponent_rating: f64, game: &GameRecord){
+        let mut stats = seat.player.stats;
+        stats.rating = rating::updated(stats.rating, opponent_rating, score);
+        if score > 0.5 {
+            stats.wins += 1;
+        } else if score < 0.5 {
+            stats.losses += 1;
+        } else {
+            stats.draws += 1;
+        }
+
+        if let Some(player) = self.players.write().await.get_mut(&seat.client_id) {
+            if player.id == seat.player.id {
+                player.stats = stats;
+            }
+        }
+        if let Err(err) = self.storage.update_stats(seat.player.id, stats).await {
+            error!("Error updating player stats: {}", err);
+        }
+        let entry = RatingEntry {
+            player_id: seat.player.id,
+            game_id: Some(game.id),
+            rating: stats.rating,
+            time: game.date,
+        };
+        if let Err(err) = self.storage.write_rating(entry).await {
+            error!("Error writing rating: {}", err);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::time;
+use tracing::trace;
+use uuid::Uuid;
+use crate::config::EngineConfig;
+use crate::tak::moves::Move;
+
+//how long an engine gets to answer anything but a search
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+//where a game stands when the engine is asked for a move
+pub(crate) struct Position {
+    pub game_id: Uuid,
+    pub size: u8,
+    pub half_komi: u8,
+    pub plies: Vec<String>,
+    //none for untimed games, which get `think_time` per move instead
+    pub clocks: Option<Clocks>,
+    pub think_time: Duration,
+}
+
+pub(crate) struct Clocks {
+    pub white: Duration,
+    pub black: Duration,
+    pub increment: Duration,
+}
+
+//a running engine, killed when dropped
+pub(crate) struct Engine {
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    pub name: Option<String>,
+    //the game the engine was last set up for
+    game_id: Option<Uuid>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn timed_out(_: time::Elapsed) -> io::Error {
+    io::Error::from(io::ErrorKind::TimedOut)
+}
+
+impl Engine {
+    //spawns the engine and waits for `teiok`
+    pub async fn start(config: &EngineConfig) -> io::Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or_else(|| invalid(String::from("no stdin")))?;
+        let stdout = child.stdout.take().ok_or_else(|| invalid(String::from("no stdout")))?;
+        let mut engine = Engine {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout).lines(),
+            name: None,
+            game_id: None,
+        };
+
+        time::timeout(HANDSHAKE_TIMEOUT, engine.handshake()).await.map_err(timed_out)??;
+        Ok(engine)
+    }
+
+    async fn handshake(&mut self) -> io::Result<()> {
+        self.send("tei").await?;
+        loop {
+            let line = self.receive().await?;
+            if line == "teiok" {
+                return Ok(());
+            }
+            if let Some(name) = line.strip_prefix("id name ") {
+                self.name = Some(String::from(name));
+            }
+        }
+    }
+
+    //asks for a move, giving up once the mover's clock could have run out
+    pub async fn best_move(&mut self, position: &Position) -> io::Result<Move> {
+        if self.game_id != Some(position.game_id) {
+            self.send(&format!("teinewgame {}", position.size)).await?;
+            if position.half_komi > 0 {
+                self.send(&format!("setoption name HalfKomi value {}", position.half_komi)).await?;
+            }
+            self.ready().await?;
+            self.game_id = Some(position.game_id);
+        }
+
+        let mut command = String::from("position startpos");
+        if !position.plies.is_empty() {
+            command.push_str(" moves ");
+            command.push_str(&position.plies.join(" "));
+        }
+        self.send(&command).await?;
+        let limit = match &position.clocks {
+            Some(clocks) => {
+                self.send(&format!("go wtime {} btime {} winc {} binc {}",
+                    clocks.white.as_millis(), clocks.black.as_millis(),
+                    clocks.increment.as_millis(), clocks.increment.as_millis())).await?;
+                if position.plies.len().is_multiple_of(2) { clocks.white } else { clocks.black }
+            },
+            None => {
+                self.send(&format!("go movetime {}", position.think_time.as_millis())).await?;
+                position.think_time
+            },
+        };
+        time::timeout(limit + HANDSHAKE_TIMEOUT, self.bestmove()).await.map_err(timed_out)?
+    }
+
+    //skips `info` and anything else the engine prints while it thinks
+    async fn bestmove(&mut self) -> io::Result<Move> {
+        loop {
+            let line = self.receive().await?;
+            if let Some(rest) = line.strip_prefix("bestmove ") {
+                let ply = rest.split_whitespace().next().unwrap_or_default();
+                return ply.parse::<Move>().map_err(|_| invalid(format!("bad move {}", ply)));
+            }
+        }
+    }
+
+    async fn ready(&mut self) -> io::Result<()> {
+        self.send("isready").await?;
+        time::timeout(HANDSHAKE_TIMEOUT, async {
+            while self.receive().await? != "readyok" {}
+            Ok(())
+        }).await.map_err(timed_out)?
+    }
+
+    async fn send(&mut self, line: &str) -> io::Result<()> {
+        trace!(line, "To engine");
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await
+    }
+
+    async fn receive(&mut self) -> io::Result<String> {
+        match self.stdout.next_line().await? {
+            Some(line) => {
+                trace!(line = %line, "From engine");
+                Ok(String::from(line.trim()))
+            },
+            None => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        }
+    }
+}
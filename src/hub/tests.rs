@@ -0,0 +1,545 @@
+use super::*;
+use crate::proto::{AcceptSeekInput, ChangePasswordInput, GameOverOutput, MoveInput, PostInput, RegisterInput, ResignInput, SeekColor, SeekInput,
+    SignInInput, SignInWithTokenInput};
+use crate::config::{BotConfig, LoginProtection};
+use crate::tak::ptn;
+use tokio::sync::broadcast::TryRecvError;
+
+//a hub backed by memory storage, driven one input at a time so every output can be asserted in order
+struct TestHub {
+    hub: Hub,
+    outputs: broadcast::Receiver<OutputParcel>,
+}
+
+impl TestHub {
+    fn new() -> Self {
+        Self::with_config(Config::default())
+    }
+
+    fn with_config(config: Config) -> Self {
+        let hub = Hub::with_storage(&config, Arc::new(MemoryStorage::new()));
+        let outputs = hub.subscribe();
+        TestHub {
+            hub,
+            outputs,
+        }
+    }
+
+    async fn connect(&self) -> Uuid {
+        let client = Client::new(Some(IpAddr::from([127, 0, 0, 1])));
+        assert!(self.hub.on_connect(client).await);
+        client.id
+    }
+
+    //processes the input and returns everything sent back to that client
+    async fn send(&mut self, client_id: Uuid, input: Input) -> Vec<Output> {
+        self.hub.process(InputParcel::new(client_id, input)).await;
+        self.outputs_for(client_id).await
+    }
+
+    //everything sent to that client since the last input
+    async fn outputs_for(&mut self, client_id: Uuid) -> Vec<Output> {
+        let signed_in = self.hub.is_signed_in(client_id).await;
+        let mut outputs = Vec::new();
+        loop {
+            match self.outputs.try_recv() {
+                Ok(parcel) if parcel.client_id == Some(client_id) => outputs.push(parcel.output),
+                Ok(parcel) if parcel.client_id.is_none() && signed_in => outputs.push(parcel.output),
+                Ok(_) => {},
+                Err(TryRecvError::Empty) => return outputs,
+                Err(err) => panic!("lost outputs: {:?}", err),
+            }
+        }
+    }
+
+    async fn register(&mut self, client_id: Uuid, name: &str) -> Vec<Output> {
+        self.send(client_id, register(name, &format!("{}@example.com", name))).await
+    }
+}
+
+fn register(name: &str, email: &str) -> Input {
+    Input::Register(RegisterInput {
+        name: String::from(name),
+        password: String::from(PASSWORD),
+        email: String::from(email),
+    })
+}
+
+fn sign_in(name: &str, password: &str) -> Input {
+    Input::SignIn(SignInInput {
+        name: String::from(name),
+        password: String::from(password),
+    })
+}
+
+fn post(body: &str) -> Input {
+    Input::Post(PostInput {
+        body: String::from(body),
+    })
+}
+
+fn seek(size: u8, komi: u8) -> Input {
+    Input::Seek(seek_input(size, komi))
+}
+
+//an unrated, untimed seek to play white
+fn seek_input(size: u8, komi: u8) -> SeekInput {
+    SeekInput {
+        size,
+        time: 0,
+        increment: 0,
+        komi,
+        color: SeekColor::White,
+        rated: false,
+        stones: None,
+        caps: None,
+        no_swap: false,
+    }
+}
+
+fn play(game_id: Uuid, ply: &str) -> Input {
+    Input::Move(MoveInput {
+        game_id,
+        ply: String::from(ply),
+    })
+}
+
+fn error(error: OutputError) -> Vec<Output> {
+    vec![Output::Error(error)]
+}
+
+fn joined(name: &str, is_guest: bool) -> Output {
+    Output::Joined(JoinedOutput::new(true, name, is_guest))
+}
+
+//pulls the token out of the session token output that follows a successful sign in
+fn session_token(outputs: &[Output]) -> String {
+    match outputs.last() {
+        Some(Output::SessionToken(session)) => session.token.clone(),
+        other => panic!("expected a session token, got {:?}", other),
+    }
+}
+
+const PASSWORD: &str = "correct horse";
+
+impl TestHub {
+    //two guests in a game started from a seek with these options, the first one playing white
+    async fn start_game(&mut self, seek: Input) -> (Uuid, Uuid, Uuid) {
+        let white = self.connect().await;
+        let black = self.connect().await;
+        self.send(white, Input::JoinAsGuest).await;
+        self.send(black, Input::JoinAsGuest).await;
+
+        let seek_id = match self.send(white, seek).await.as_slice() {
+            [Output::SeekAdded(seek)] => seek.id,
+            other => panic!("expected the seek back, got {:?}", other),
+        };
+        match self.send(black, Input::AcceptSeek(AcceptSeekInput { id: seek_id })).await.as_slice() {
+            [Output::SeekRemoved(_), Output::GameStarted(started)] => (white, black, started.game_id),
+            other => panic!("expected the game to start, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn guests_get_unique_names() {
+    let mut hub = TestHub::new();
+    let first = hub.connect().await;
+    let second = hub.connect().await;
+
+    assert_eq!(hub.send(first, Input::JoinAsGuest).await, vec![joined("Guest1", true)]);
+    assert_eq!(hub.send(second, Input::JoinAsGuest).await, vec![joined("Guest2", true)]);
+    assert_eq!(hub.send(first, Input::JoinAsGuest).await, error(OutputError::AlreadySignedIn));
+}
+
+#[tokio::test]
+async fn posting_requires_joining() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+
+    assert_eq!(hub.send(client, post("hello")).await, error(OutputError::NotJoined));
+    hub.send(client, Input::JoinAsGuest).await;
+    assert_eq!(hub.send(client, post("hello")).await, vec![Output::Message(MessageOutput::new(String::from("hello")))]);
+    assert_eq!(hub.send(client, post("")).await, error(OutputError::InvalidMessageBody));
+    assert_eq!(hub.send(client, post(&"a".repeat(MAX_GUEST_MESSAGE_BODY_LENGTH + 1))).await, error(OutputError::InvalidMessageBody));
+}
+
+#[tokio::test]
+async fn registering_joins_and_issues_a_token() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+
+    let outputs = hub.register(client, "Alice").await;
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[0], joined("Alice", false));
+    session_token(&outputs);
+
+    assert_eq!(hub.register(client, "Bob").await, error(OutputError::AlreadySignedIn));
+}
+
+#[tokio::test]
+async fn registering_rejects_bad_names() {
+    let mut hub = TestHub::new();
+    hub.register(hub.connect().await, "Player_One").await;
+
+    //registering is rate limited per client, so every attempt gets a fresh connection
+    let cases = vec![
+        ("no", OutputError::InvalidName),
+        ("Guest12", OutputError::ReservedName),
+        ("Adm1n", OutputError::ReservedName),
+        ("player_one", OutputError::NameTaken),
+        ("PlayerOne", OutputError::SimilarNameTaken),
+    ];
+    for (name, expected) in cases {
+        let client = hub.connect().await;
+        assert_eq!(hub.register(client, name).await, error(expected), "registering {}", name);
+    }
+    let client = hub.connect().await;
+    assert_eq!(hub.send(client, register("Carol", "not an email")).await, error(OutputError::InvalidEmail));
+}
+
+#[tokio::test]
+async fn the_bots_name_is_reserved() {
+    let mut hub = TestHub::with_config(Config {
+        bot: Some(BotConfig::default()),
+        ..Default::default()
+    });
+    let client = hub.connect().await;
+    assert_eq!(hub.register(client, "PracticeBot").await, error(OutputError::ReservedName));
+    assert_eq!(hub.register(client, "Practice_B0t").await, error(OutputError::ReservedName));
+}
+
+#[tokio::test]
+async fn guests_can_register_in_place() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+
+    hub.send(client, Input::JoinAsGuest).await;
+    let outputs = hub.register(client, "Dave").await;
+    assert_eq!(outputs[0], joined("Dave", false));
+}
+
+#[tokio::test]
+async fn guests_register_only_when_idle() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+    hub.send(client, Input::JoinAsGuest).await;
+
+    hub.send(client, seek(5, 0)).await;
+    assert_eq!(hub.register(client, "Erin").await, error(OutputError::SeekOpen));
+    hub.send(client, Input::CancelSeek).await;
+    assert_eq!(hub.register(client, "Erin").await[0], joined("Erin", false));
+
+    let (white, _, _) = hub.start_game(seek(5, 0)).await;
+    assert_eq!(hub.register(white, "Fern").await, error(OutputError::AlreadyInGame));
+}
+
+#[tokio::test]
+async fn signing_in_checks_the_password() {
+    let mut hub = TestHub::new();
+    let first = hub.connect().await;
+    hub.register(first, "Erin").await;
+
+    let second = hub.connect().await;
+    assert_eq!(hub.send(second, sign_in("Erin", PASSWORD)).await, error(OutputError::NameTaken));
+    hub.hub.on_disconnect(first).await;
+
+    assert_eq!(hub.send(second, sign_in("Erin", "wrong")).await, error(OutputError::InvalidPassword));
+    assert_eq!(hub.send(second, sign_in("Nobody", PASSWORD)).await, error(OutputError::PlayerNotFound));
+    assert_eq!(hub.send(second, sign_in("Guest3", PASSWORD)).await, error(OutputError::InvalidName));
+
+    let outputs = hub.send(second, sign_in("erin", PASSWORD)).await;
+    assert_eq!(outputs[0], joined("Erin", false));
+    session_token(&outputs);
+}
+
+#[tokio::test]
+async fn repeated_failures_lock_signing_in() {
+    let mut config = Config::default();
+    config.login_protection.backoff_base = 60;
+    let mut hub = TestHub::with_config(config);
+    let first = hub.connect().await;
+    hub.register(first, "Frank").await;
+    hub.hub.on_disconnect(first).await;
+
+    let client = hub.connect().await;
+    //every spelling that finds the account counts against it
+    for name in &["Frank", "frank", " FRANK "] {
+        assert_eq!(hub.send(client, sign_in(name, "wrong")).await, error(OutputError::InvalidPassword));
+    }
+    match hub.send(client, sign_in("Frank", PASSWORD)).await.as_slice() {
+        [Output::Error(OutputError::SignInLocked { retry_after })] => assert!(*retry_after > 0),
+        other => panic!("expected a lockout, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn changing_the_password_counts_failures() {
+    let login_protection = LoginProtection { backoff_base: 60, ..LoginProtection::default() };
+    let mut hub = TestHub::with_config(Config { login_protection, ..Config::default() });
+    let client = hub.connect().await;
+    hub.register(client, "Heidi").await;
+
+    let change = |password: &str| Input::ChangePassword(ChangePasswordInput {
+        password: String::from(password),
+        new_password: String::from("battery staple"),
+    });
+    for _ in 0..3 {
+        assert_eq!(hub.send(client, change("wrong")).await, error(OutputError::InvalidPassword));
+    }
+    match hub.send(client, change(PASSWORD)).await.as_slice() {
+        [Output::Error(OutputError::SignInLocked { retry_after })] => assert!(*retry_after > 0),
+        other => panic!("expected a lockout, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn tokens_sign_in_until_the_password_changes() {
+    let mut hub = TestHub::new();
+    let first = hub.connect().await;
+    let token = session_token(&hub.register(first, "Grace").await);
+    hub.hub.on_disconnect(first).await;
+
+    let second = hub.connect().await;
+    let with_token = Input::SignInWithToken(SignInWithTokenInput { token: token.clone() });
+    assert_eq!(hub.send(second, with_token.clone()).await, vec![joined("Grace", false)]);
+
+    let change = Input::ChangePassword(ChangePasswordInput {
+        password: String::from(PASSWORD),
+        new_password: String::from("battery staple"),
+    });
+    let outputs = hub.send(second, change).await;
+    assert_eq!(outputs[0], Output::PasswordChanged);
+    session_token(&outputs);
+    hub.hub.on_disconnect(second).await;
+
+    let third = hub.connect().await;
+    assert_eq!(hub.send(third, with_token).await, error(OutputError::InvalidToken));
+    let garbage = Input::SignInWithToken(SignInWithTokenInput { token: String::from("garbage") });
+    assert_eq!(hub.send(third, garbage).await, error(OutputError::InvalidToken));
+}
+
+#[tokio::test]
+async fn player_info_reports_presence() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+    hub.register(client, "Heidi").await;
+
+    let info = Input::PlayerInfo(PlayerInfoInput { name: String::from("heidi") });
+    match hub.send(client, info.clone()).await.as_slice() {
+        [Output::PlayerInfo(info)] => {
+            assert_eq!(info.name, "Heidi");
+            assert!(info.online);
+            assert_eq!(info.games_played, 0);
+        },
+        other => panic!("expected player info, got {:?}", other),
+    }
+
+    let missing = Input::PlayerInfo(PlayerInfoInput { name: String::from("Ivan") });
+    assert_eq!(hub.send(client, missing).await, error(OutputError::PlayerNotFound));
+}
+
+#[tokio::test]
+async fn posting_is_rate_limited() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+    hub.send(client, Input::JoinAsGuest).await;
+
+    let burst = Config::default().rate_limits.guest_post.client.burst;
+    for _ in 0..burst {
+        assert_eq!(hub.send(client, post("hi")).await.len(), 1);
+    }
+    assert_eq!(hub.send(client, post("hi")).await, error(OutputError::RateLimited));
+}
+
+#[tokio::test]
+async fn connections_are_capped_per_ip() {
+    let mut config = Config::default();
+    config.max_connections_per_ip = 2;
+    let hub = TestHub::with_config(config);
+    let ip = Some(IpAddr::from([10, 0, 0, 1]));
+
+    let first = Client::new(ip);
+    assert!(hub.hub.on_connect(first).await);
+    assert!(hub.hub.on_connect(Client::new(ip)).await);
+    assert!(!hub.hub.on_connect(Client::new(ip)).await);
+
+    hub.hub.on_disconnect(first.id).await;
+    assert!(hub.hub.on_connect(Client::new(ip)).await);
+}
+
+#[tokio::test]
+async fn komi_decides_flat_wins() {
+    //a full checkerboard leaves white one flat ahead and nobody with a road, the first two plies swap colors
+    let plies = ["b1", "a1", "c1", "a2", "b2", "c2", "a3", "b3", "c3"];
+    for (komi, result) in vec![(0, "F-0"), (2, "1/2-1/2"), (3, "0-F")] {
+        let mut hub = TestHub::new();
+        let (white, black, game_id) = hub.start_game(seek(3, komi)).await;
+        for (number, ply) in plies.iter().enumerate() {
+            let mover = if number % 2 == 0 { white } else { black };
+            hub.send(mover, play(game_id, ply)).await;
+        }
+
+        let game = hub.hub.storage.get_game(game_id).await.unwrap().expect("the game is stored");
+        assert_eq!(game.result, result, "komi {}", komi);
+        assert_eq!(game.komi, komi as f64 / 2.0);
+        assert!(ptn::document(&game).contains(&format!("[Komi \"{}\"]", game.komi)));
+    }
+}
+
+#[tokio::test]
+async fn illegal_moves_leave_the_clock_alone() {
+    let mut hub = TestHub::new();
+    let mut timed = seek_input(5, 0);
+    timed.time = 60;
+    timed.increment = 30;
+    let (white, _, game_id) = hub.start_game(Input::Seek(timed)).await;
+
+    for _ in 0..5 {
+        assert_eq!(hub.send(white, play(game_id, "f1")).await, error(OutputError::IllegalMove));
+    }
+    assert_eq!(hub.hub.games.read().await[&game_id].white.clock, Duration::from_secs(60));
+    match hub.send(white, play(game_id, "a1")).await.as_slice() {
+        [Output::Moved(moved)] => assert!(moved.white_time <= 90_000 && moved.white_time > 89_000, "{}", moved.white_time),
+        other => panic!("expected the move back, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn moves_are_checked() {
+    let mut hub = TestHub::new();
+    let (white, black, game_id) = hub.start_game(seek(5, 0)).await;
+
+    assert_eq!(hub.send(black, play(game_id, "a1")).await, error(OutputError::NotYourTurn));
+    assert_eq!(hub.send(white, play(game_id, "f1")).await, error(OutputError::IllegalMove));
+    assert_eq!(hub.send(white, play(Uuid::new_v4(), "a1")).await, error(OutputError::GameNotFound));
+    match hub.send(white, play(game_id, "a1")).await.as_slice() {
+        [Output::Moved(moved)] => assert_eq!(moved.ply, "a1"),
+        other => panic!("expected the move back, got {:?}", other),
+    }
+    assert_eq!(hub.send(black, play(game_id, "a1")).await, error(OutputError::IllegalMove));
+    assert_eq!(hub.send(white, seek(5, 0)).await, error(OutputError::AlreadyInGame));
+    assert_eq!(hub.send(white, seek(9, 0)).await, error(OutputError::AlreadyInGame));
+}
+
+#[tokio::test]
+async fn seeks_are_validated() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+    assert_eq!(hub.send(client, seek(5, 0)).await, error(OutputError::NotJoined));
+    hub.send(client, Input::JoinAsGuest).await;
+
+    assert_eq!(hub.send(client, seek(9, 0)).await, error(OutputError::InvalidSeek));
+    assert_eq!(hub.send(client, seek(5, 20)).await, error(OutputError::InvalidSeek));
+    //guests only play unrated games
+    let rated = Input::Seek(SeekInput { rated: true, ..seek_input(5, 0) });
+    assert_eq!(hub.send(client, rated).await, error(OutputError::InvalidSeek));
+    let no_stones = Input::Seek(SeekInput { stones: Some(0), ..seek_input(5, 0) });
+    assert_eq!(hub.send(client, no_stones).await, error(OutputError::InvalidSeek));
+    let too_many = Input::Seek(SeekInput { caps: Some(5), ..seek_input(4, 0) });
+    assert_eq!(hub.send(client, too_many).await, error(OutputError::InvalidSeek));
+    assert_eq!(hub.send(client, Input::CancelSeek).await, error(OutputError::SeekNotFound));
+}
+
+#[tokio::test]
+async fn seeks_reach_a_crowded_lobby() {
+    let mut hub = TestHub::new();
+    let lurker = hub.connect().await;
+    let mut guests = Vec::new();
+    //each from its own address, joining is limited per ip
+    for last in 1..=20 {
+        let client = Client::new(Some(IpAddr::from([10, 0, 0, last])));
+        assert!(hub.hub.on_connect(client).await);
+        hub.send(client.id, Input::JoinAsGuest).await;
+        guests.push(client.id);
+    }
+
+    //more players than the output channel holds, one parcel reaches them all
+    hub.hub.process(InputParcel::new(guests[0], seek(5, 0))).await;
+    let seen = hub.outputs_for(guests[19]).await;
+    assert!(matches!(seen.as_slice(), [Output::SeekAdded(_)]), "got {:?}", seen);
+    //connections that have not joined stay out of the lobby
+    hub.hub.process(InputParcel::new(guests[0], Input::CancelSeek)).await;
+    assert_eq!(hub.outputs_for(lurker).await, vec![]);
+}
+
+#[tokio::test]
+async fn seeks_carry_piece_counts() {
+    let mut hub = TestHub::new();
+    let client = hub.connect().await;
+    hub.send(client, Input::JoinAsGuest).await;
+
+    for (size, stones, caps) in vec![(7, 40, 2), (8, 50, 2)] {
+        match hub.send(client, seek(size, 0)).await.last() {
+            Some(Output::SeekAdded(seek)) => assert_eq!((seek.stones, seek.caps), (stones, caps), "size {}", size),
+            other => panic!("expected the seek back, got {:?}", other),
+        }
+    }
+
+    //white runs out of pieces on their second move, with one flat each on the board after the swap
+    let short = SeekInput { stones: Some(1), caps: Some(1), ..seek_input(6, 0) };
+    let (white, black, game_id) = hub.start_game(Input::Seek(short)).await;
+    hub.send(white, play(game_id, "a1")).await;
+    hub.send(black, play(game_id, "b1")).await;
+    assert_eq!(hub.send(white, play(game_id, "c1")).await, error(OutputError::IllegalMove));
+    hub.send(white, play(game_id, "Cc1")).await;
+
+    let game = hub.hub.storage.get_game(game_id).await.unwrap().expect("the game is stored");
+    assert_eq!((game.result.as_str(), game.stones, game.caps), ("1/2-1/2", 1, 1));
+    let document = ptn::document(&game);
+    assert!(document.contains("[Flats \"1\"]") && document.contains("[Caps \"1\"]"));
+}
+
+#[tokio::test]
+async fn openings_place_the_opponents_flat() {
+    let mut hub = TestHub::new();
+    let (white, black, game_id) = hub.start_game(seek(5, 0)).await;
+    assert_eq!(hub.send(white, play(game_id, "Sa1")).await, error(OutputError::IllegalMove));
+    assert_eq!(hub.send(white, play(game_id, "Ca1")).await, error(OutputError::IllegalMove));
+    hub.send(white, play(game_id, "a1")).await;
+    //the flat on a1 is black's, but black may not move it yet
+    assert_eq!(hub.send(black, play(game_id, "a1+")).await, error(OutputError::IllegalMove));
+    hub.send(black, play(game_id, "e5")).await;
+    assert_eq!(hub.send(white, play(game_id, "a1+")).await, error(OutputError::IllegalMove));
+    assert_eq!(hub.send(white, play(game_id, "e5-")).await.len(), 1);
+
+    let mut hub = TestHub::new();
+    let no_swap = SeekInput { no_swap: true, ..seek_input(5, 0) };
+    let (white, _, game_id) = hub.start_game(Input::Seek(no_swap)).await;
+    assert_eq!(hub.send(white, play(game_id, "Ca1")).await.len(), 1);
+    hub.send(white, Input::Resign(ResignInput { game_id })).await;
+    let game = hub.hub.storage.get_game(game_id).await.unwrap().expect("the game is stored");
+    assert!(game.no_swap);
+    assert!(ptn::document(&game).contains("[Opening \"no-swap\"]"));
+}
+
+#[tokio::test]
+async fn moves_announce_tak() {
+    let mut hub = TestHub::new();
+    let (white, black, game_id) = hub.start_game(seek(4, 0)).await;
+    let mut tak = Vec::new();
+    //white builds a1 b1 c1 and threatens d1, black answers on d1 with a wall
+    for (mover, ply) in vec![(white, "d4"), (black, "a1"), (white, "b1"), (black, "a4"), (white, "c1"), (black, "Sd1")] {
+        match hub.send(mover, play(game_id, ply)).await.as_slice() {
+            [Output::Moved(moved)] => tak.push(moved.tak),
+            other => panic!("expected {} to be played, got {:?}", ply, other),
+        }
+    }
+    assert_eq!(tak, vec![false, false, false, false, true, false]);
+}
+
+#[tokio::test]
+async fn idle_players_lose_on_time() {
+    let mut hub = TestHub::new();
+    let mut timed = seek_input(5, 0);
+    timed.time = 1;
+    let (white, _, game_id) = hub.start_game(Input::Seek(timed)).await;
+
+    hub.hub.flag_idle_players().await;
+    assert_eq!(hub.outputs_for(white).await, vec![]);
+    time::delay_for(Duration::from_millis(1100)).await;
+    hub.hub.flag_idle_players().await;
+    let over = vec![Output::GameOver(GameOverOutput { game_id, result: String::from("0-1") })];
+    assert_eq!(hub.outputs_for(white).await, over);
+    assert_eq!(hub.send(white, play(game_id, "a1")).await, error(OutputError::GameNotFound));
+}
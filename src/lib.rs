@@ -1,11 +1,22 @@
 pub mod server;
 mod hub;
-mod model;
+pub mod model;
 mod proto;
 mod client;
 mod error;
-mod tak;
-mod database;
+pub mod tak;
+pub mod database;
+pub mod storage;
+pub mod config;
+pub mod auth;
+pub mod logging;
+mod rate_limit;
+mod login_guard;
+mod session;
+mod clock;
+mod names;
+mod metrics;
+mod api;
 
 #[macro_use(lazy_static)]
 extern crate lazy_static;
\ No newline at end of file
@@ -0,0 +1,13 @@
+use tracing_subscriber::EnvFilter;
+use crate::config::LogFormat;
+
+//installs the global subscriber, filtered by `RUST_LOG` and defaulting to info
+//events from the `log` macros are picked up too and carry the fields of the span they happen in
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_current_span(true).with_span_list(false).init(),
+    }
+}
@@ -0,0 +1,74 @@
+use std::net::IpAddr;
+use log::warn;
+use crate::config::LoginProtection;
+use crate::storage::Storage;
+use crate::error::Error;
+use crate::clock::unix_time;
+
+pub struct LoginGuard {
+    protection: LoginProtection,
+}
+
+impl LoginGuard {
+    pub fn new(protection: LoginProtection) -> Self {
+        LoginGuard {
+            protection,
+        }
+    }
+
+    //seconds to wait after `failures` consecutive failed attempts on one account
+    fn account_delay(&self, failures: u32) -> u64 {
+        if failures >= self.protection.lockout_threshold {
+            self.protection.lockout_duration
+        } else if failures >= self.protection.free_attempts {
+            let exponent = (failures - self.protection.free_attempts).min(32);
+            self.protection.backoff_base
+                .saturating_mul(1u64 << exponent)
+                .min(self.protection.max_backoff)
+        } else {
+            0
+        }
+    }
+
+    //returns how many seconds the caller must wait before trying to sign in again, if any
+    pub async fn retry_after(&self, storage: &dyn Storage, user_name: &str, ip: Option<IpAddr>) -> Result<Option<u64>, Error> {
+        let now = unix_time();
+        let since = now - self.protection.window as i64;
+
+        let (failures, last_failure) = storage.account_login_failures(user_name, since).await?;
+        let mut wait_until = last_failure + self.account_delay(failures) as i64;
+
+        if let Some(ip) = ip {
+            let (ip_failures, ip_last_failure) = storage.ip_login_failures(ip, since).await?;
+            if ip_failures >= self.protection.ip_lockout_threshold {
+                wait_until = wait_until.max(ip_last_failure + self.protection.lockout_duration as i64);
+            }
+        }
+
+        if wait_until > now {
+            Ok(Some((wait_until - now) as u64))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn record(&self, storage: &dyn Storage, user_name: &str, ip: Option<IpAddr>, success: bool) -> Result<(), Error> {
+        storage.record_login_attempt(user_name, ip, success, unix_time()).await?;
+        if success {
+            return Ok(());
+        }
+
+        let since = unix_time() - self.protection.window as i64;
+        let (failures, _) = storage.account_login_failures(user_name, since).await?;
+        if failures == self.protection.lockout_threshold {
+            warn!("Account {} locked after {} failed sign in attempts, last from {:?}", user_name, failures, ip);
+        }
+        if let Some(ip) = ip {
+            let (ip_failures, _) = storage.ip_login_failures(ip, since).await?;
+            if ip_failures == self.protection.ip_lockout_threshold {
+                warn!("Ip {} locked out after {} failed sign in attempts across accounts", ip, ip_failures);
+            }
+        }
+        Ok(())
+    }
+}
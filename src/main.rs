@@ -1,8 +1,125 @@
 use playtak_ws::server::ExServer;
+use playtak_ws::config::{Config, StorageKind};
+use playtak_ws::database::{migrations, legacy, Database};
+use playtak_ws::storage::issue_password_reset;
+use playtak_ws::logging;
 
 #[tokio::main]
 async fn main(){
-    env_logger::init();
-    let server : ExServer = ExServer::new(8000);
+    let config = match std::env::var("PLAYTAK_CONFIG") {
+        Ok(path) => Config::from_file(&path).unwrap_or_else(|err| {
+            eprintln!("Could not load config from {}: {}", path, err);
+            std::process::exit(1);
+        }),
+        Err(_) => Config::default(),
+    };
+    logging::init(config.log_format);
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        std::process::exit(migrate(&config, args.get(2).map(String::as_str)));
+    }
+    if args.get(1).map(String::as_str) == Some("import") {
+        std::process::exit(import(&config, &args[2..]));
+    }
+    if args.get(1).map(String::as_str) == Some("reset-password") {
+        std::process::exit(reset_password(&config, &args[2..]).await);
+    }
+
+    //stored sessions outlive the process, a secret made up on start up would leave them all unverifiable
+    if config.token_secret.is_none() && config.storage != StorageKind::Memory {
+        eprintln!("tokenSecret must be set unless storage is memory");
+        std::process::exit(1);
+    }
+
+    let server : ExServer = ExServer::with_config(8000, config);
     server.run().await;
-}
\ No newline at end of file
+}
+
+//`migrate [status|run]`, returns the process exit code
+fn migrate(config: &Config, command: Option<&str>) -> i32 {
+    match command.unwrap_or("status") {
+        "status" => match migrations::status(&config.database_path) {
+            Ok((version, pending)) => {
+                println!("{} is at schema version {}", config.database_path, version);
+                for migration in &pending {
+                    println!("pending {}: {}", migration.version, migration.description);
+                }
+                if pending.is_empty() {
+                    println!("no pending migrations");
+                }
+                0
+            },
+            Err(err) => {
+                eprintln!("Could not read schema version: {}", err);
+                1
+            }
+        },
+        "run" => match migrations::run_pending(&config.database_path) {
+            Ok(applied) => {
+                println!("applied {} migrations", applied);
+                0
+            },
+            Err(err) => {
+                eprintln!("Migration failed: {}", err);
+                1
+            }
+        },
+        other => {
+            eprintln!("unknown migrate command {}, expected status or run", other);
+            2
+        }
+    }
+}
+
+//`import <legacy players db> [legacy games db]`, the games table is read from the players db when no second file is given
+fn import(config: &Config, paths: &[String]) -> i32 {
+    let (players_path, games_path) = match paths {
+        [players] => (players, players),
+        [players, games] => (players, games),
+        _ => {
+            eprintln!("usage: import <legacy players db> [legacy games db]");
+            return 2;
+        }
+    };
+
+    match legacy::import(&config.database_path, players_path, games_path) {
+        Ok(summary) => {
+            println!("imported {} players ({} must reset their password, {} skipped)",
+                summary.players, summary.flagged_for_reset, summary.skipped_players);
+            println!("imported {} games ({} skipped)", summary.games, summary.skipped_games);
+            0
+        },
+        Err(err) => {
+            eprintln!("Import failed, nothing was written: {}", err);
+            1
+        }
+    }
+}
+//`reset-password <name>`, prints a one time token to pass on to the player, who redeems it with `resetPassword`
+async fn reset_password(config: &Config, args: &[String]) -> i32 {
+    let name = match args {
+        [name] => name,
+        _ => {
+            eprintln!("usage: reset-password <name>");
+            return 2;
+        }
+    };
+
+    let database = Database::open(&config.database_path, 1);
+    match issue_password_reset(&database, name).await {
+        Ok(Some((token, expires))) => {
+            println!("reset token for {}: {}", name, token);
+            println!("valid until {} (unix time)", expires);
+            0
+        },
+        Ok(None) => {
+            eprintln!("no player named {}", name);
+            1
+        },
+        Err(err) => {
+            eprintln!("Could not issue a reset token: {}", err);
+            1
+        }
+    }
+}
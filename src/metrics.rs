@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+//upper bounds of the database latency histogram, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+//process wide counters, rendered in the prometheus text format by the `/metrics` route
+#[derive(Default)]
+pub struct Metrics {
+    inputs: Mutex<BTreeMap<&'static str, u64>>,
+    outputs: Mutex<BTreeMap<&'static str, u64>>,
+    errors: Mutex<BTreeMap<String, u64>>,
+    broadcast_lagged: AtomicU64,
+    database: Mutex<Latency>,
+    database_errors: AtomicU64,
+}
+
+#[derive(Default)]
+struct Latency {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+//values read from the hub when the metrics are scraped
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gauges {
+    pub clients: usize,
+    pub players: usize,
+    pub guests: usize,
+    pub games: usize,
+    pub seeks: usize,
+}
+
+fn increment<K: Ord>(counters: &Mutex<BTreeMap<K, u64>>, key: K) {
+    if let Ok(mut counters) = counters.lock() {
+        *counters.entry(key).or_insert(0) += 1;
+    }
+}
+
+impl Metrics {
+    pub fn input(&self, kind: &'static str) {
+        increment(&self.inputs, kind);
+    }
+
+    pub fn output(&self, kind: &'static str) {
+        increment(&self.outputs, kind);
+    }
+
+    //errors reported to clients, by protocol error code
+    pub fn error(&self, code: &str) {
+        increment(&self.errors, String::from(code));
+    }
+
+    //outputs a slow connection missed because the broadcast channel moved past them
+    pub fn broadcast_lagged(&self, missed: u64) {
+        self.broadcast_lagged.fetch_add(missed, Ordering::Relaxed);
+    }
+
+    pub fn database_query(&self, elapsed: Duration, failed: bool) {
+        if failed {
+            self.database_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Ok(mut latency) = self.database.lock() {
+            let seconds = elapsed.as_secs_f64();
+            if latency.buckets.is_empty() {
+                latency.buckets = vec![0; LATENCY_BUCKETS.len()];
+            }
+            for (count, bound) in latency.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+                if seconds <= *bound {
+                    *count += 1;
+                }
+            }
+            latency.sum += seconds;
+            latency.count += 1;
+        }
+    }
+
+    pub fn render(&self, gauges: Gauges) -> String {
+        let mut out = String::new();
+        gauge(&mut out, "playtak_connected_clients", "Open websocket connections.", gauges.clients);
+        gauge(&mut out, "playtak_signed_in_players", "Players signed in to an account.", gauges.players);
+        gauge(&mut out, "playtak_guests", "Players joined as guests.", gauges.guests);
+        gauge(&mut out, "playtak_active_games", "Games in progress.", gauges.games);
+        gauge(&mut out, "playtak_open_seeks", "Seeks waiting for an opponent.", gauges.seeks);
+
+        counters(&mut out, "playtak_inputs_total", "Inputs received, by type.", "type", &self.inputs);
+        counters(&mut out, "playtak_outputs_total", "Outputs sent, by type.", "type", &self.outputs);
+        counters(&mut out, "playtak_errors_total", "Errors reported to clients, by code.", "code", &self.errors);
+
+        header(&mut out, "playtak_broadcast_lagged_total", "Outputs dropped for connections that fell behind.", "counter");
+        let _ = writeln!(out, "playtak_broadcast_lagged_total {}", self.broadcast_lagged.load(Ordering::Relaxed));
+        header(&mut out, "playtak_database_errors_total", "Database queries that failed.", "counter");
+        let _ = writeln!(out, "playtak_database_errors_total {}", self.database_errors.load(Ordering::Relaxed));
+
+        header(&mut out, "playtak_database_query_seconds", "Time spent running database queries.", "histogram");
+        if let Ok(latency) = self.database.lock() {
+            for (index, bound) in LATENCY_BUCKETS.iter().enumerate() {
+                let count = latency.buckets.get(index).copied().unwrap_or(0);
+                let _ = writeln!(out, "playtak_database_query_seconds_bucket{{le=\"{}\"}} {}", bound, count);
+            }
+            let _ = writeln!(out, "playtak_database_query_seconds_bucket{{le=\"+Inf\"}} {}", latency.count);
+            let _ = writeln!(out, "playtak_database_query_seconds_sum {}", latency.sum);
+            let _ = writeln!(out, "playtak_database_query_seconds_count {}", latency.count);
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counters<K: Ord + std::fmt::Display>(out: &mut String, name: &str, help: &str, label: &str, counters: &Mutex<BTreeMap<K, u64>>) {
+    header(out, name, help, "counter");
+    if let Ok(counters) = counters.lock() {
+        for (key, count) in counters.iter() {
+            let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, key, count);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use uuid::Uuid;
+use crate::names::name_key;
+
+//a finished game as it is kept in storage, `notation` holds the ptn move list
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub id: Uuid,
+    pub date: i64,
+    pub size: u8,
+    pub white: String,
+    pub black: String,
+    pub notation: String,
+    pub result: String,
+    pub rated: bool,
+    pub white_rating: f64,
+    pub black_rating: f64,
+    pub time: u32,
+    pub increment: u32,
+    //flats added to black's count when the game ends on flats, in halves as small as 0.5
+    pub komi: f64,
+    //pieces each player starts with, walls count as stones
+    pub stones: u8,
+    pub caps: u8,
+    //played without the swap opening
+    pub no_swap: bool,
+    //set for tournament games
+    pub event: Option<String>,
+    pub round: Option<String>,
+}
+
+//a player's rating after a game, or after a rebuild when there is no game
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatingEntry {
+    pub player_id: Uuid,
+    pub game_id: Option<Uuid>,
+    pub rating: f64,
+    pub time: i64,
+}
+
+//which games to list and which page of them, newest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameFilter {
+    //either side, compared by name key like account lookups, see `names::name_key`
+    pub player: Option<String>,
+    pub size: Option<u8>,
+    pub rated: Option<bool>,
+    //unix times, exclusive
+    pub before: Option<i64>,
+    pub after: Option<i64>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+impl Default for GameFilter {
+    fn default() -> Self {
+        GameFilter {
+            player: None,
+            size: None,
+            rated: None,
+            before: None,
+            after: None,
+            limit: 50,
+            offset: 0,
+        }
+    }
+}
+
+impl GameFilter {
+    pub fn matches(&self, game: &GameRecord) -> bool {
+        self.player.as_ref().map(|player| name_key(player))
+            .is_none_or(|key| name_key(&game.white) == key || name_key(&game.black) == key)
+            && self.size.is_none_or(|size| game.size == size)
+            && self.rated.is_none_or(|rated| game.rated == rated)
+            && self.before.is_none_or(|before| game.date < before)
+            && self.after.is_none_or(|after| game.date > after)
+    }
+}
+
+impl GameRecord {
+    //white's score for rating purposes, none for unfinished or aborted games
+    pub fn white_score(&self) -> Option<f64> {
+        match self.result.as_str() {
+            "R-0" | "F-0" | "1-0" => Some(1.0),
+            "0-R" | "0-F" | "0-1" => Some(0.0),
+            "1/2-1/2" => Some(0.5),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use regex::Regex;
+
+pub const MAX_NAME_LENGTH: usize = 24;
+
+lazy_static! {
+    pub static ref USER_NAME_REGEX: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9_]{3,23}$").unwrap();
+    pub static ref GUEST_NAME_REGEX: Regex = Regex::new(r"(?i)^Guest\d*$").unwrap();
+}
+
+//compared by skeleton, so look-alike spellings such as `Adm1n` are caught too
+const RESERVED_NAMES: &[&str] = &[
+    "admin", "administrator", "moderator", "mod", "staff", "support", "system", "server",
+    "root", "playtak", "tak", "anonymous", "anon", "nobody", "null", "undefined",
+];
+
+//characters that read alike once lowercased, applied in order, `rn` before the single letter swaps
+//migration 7 spells the same folding out in sql, keep the two in step
+const CONFUSABLES: &[(&str, &str)] = &[
+    ("_", ""), (" ", ""), ("rn", "m"), ("vv", "w"), ("0", "o"), ("1", "l"), ("i", "l"), ("5", "s"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NameProblem {
+    Invalid,
+    Reserved,
+}
+
+//the identity of an account, two names with the same key are the same player
+pub fn name_key(name: &str) -> String {
+    name.trim().to_ascii_lowercase()
+}
+
+//names with the same skeleton are too easy to mistake for each other to both be registered
+pub fn name_skeleton(name: &str) -> String {
+    CONFUSABLES.iter().fold(name_key(name), |skeleton, (from, to)| skeleton.replace(from, to))
+}
+
+//checks a name someone wants to register, look-alikes of existing accounts are checked against storage
+pub fn validate_new_name(name: &str) -> Result<(), NameProblem> {
+    if !USER_NAME_REGEX.is_match(name) {
+        return Err(NameProblem::Invalid);
+    }
+    let skeleton = name_skeleton(name);
+    if GUEST_NAME_REGEX.is_match(name) || RESERVED_NAMES.iter().any(|reserved| name_skeleton(reserved) == skeleton) {
+        return Err(NameProblem::Reserved);
+    }
+    Ok(())
+}
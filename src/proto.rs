@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use crate::tak::player::Player;
 
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -10,7 +11,60 @@ pub enum Input {
     #[serde(rename = "post")]
     Post(PostInput),
     #[serde(rename = "signIn")]
-    SignIn(SignInInput)
+    SignIn(SignInInput),
+    #[serde(rename = "signInWithToken")]
+    SignInWithToken(SignInWithTokenInput),
+    #[serde(rename = "changePassword")]
+    ChangePassword(ChangePasswordInput),
+    #[serde(rename = "resetPassword")]
+    ResetPassword(ResetPasswordInput),
+    #[serde(rename = "joinAsGuest")]
+    JoinAsGuest,
+    #[serde(rename = "playerInfo")]
+    PlayerInfo(PlayerInfoInput),
+    #[serde(rename = "seek")]
+    Seek(SeekInput),
+    #[serde(rename = "cancelSeek")]
+    CancelSeek,
+    #[serde(rename = "listSeeks")]
+    ListSeeks,
+    #[serde(rename = "acceptSeek")]
+    AcceptSeek(AcceptSeekInput),
+    #[serde(rename = "move")]
+    Move(MoveInput),
+    #[serde(rename = "resign")]
+    Resign(ResignInput),
+}
+
+impl Input {
+    //the game a move or resignation is for
+    pub fn game_id(&self) -> Option<Uuid> {
+        match self {
+            Input::Move(input) => Some(input.game_id),
+            Input::Resign(input) => Some(input.game_id),
+            _ => None,
+        }
+    }
+
+    //the wire name of the input, used to label logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Input::Register(_) => "register",
+            Input::Post(_) => "post",
+            Input::SignIn(_) => "signIn",
+            Input::SignInWithToken(_) => "signInWithToken",
+            Input::ChangePassword(_) => "changePassword",
+            Input::ResetPassword(_) => "resetPassword",
+            Input::JoinAsGuest => "joinAsGuest",
+            Input::PlayerInfo(_) => "playerInfo",
+            Input::Seek(_) => "seek",
+            Input::CancelSeek => "cancelSeek",
+            Input::ListSeeks => "listSeeks",
+            Input::AcceptSeek(_) => "acceptSeek",
+            Input::Move(_) => "move",
+            Input::Resign(_) => "resign",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -28,12 +82,92 @@ pub struct SignInInput {
     pub password: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignInWithTokenInput {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordInput {
+    pub password: String,
+    pub new_password: String,
+}
+
+//redeems a one time token from `playtak reset-password`, signing in is left to the client
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordInput {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerInfoInput {
+    pub name: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PostInput {
     pub body: String,
 }
 
+//times are in seconds, a `time` of 0 plays without a clock
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeekInput {
+    pub size: u8,
+    pub time: u32,
+    pub increment: u32,
+    //in half flats, so 3 gives black 1.5 flats
+    #[serde(default)]
+    pub komi: u8,
+    #[serde(default)]
+    pub color: SeekColor,
+    #[serde(default)]
+    pub rated: bool,
+    //the rule book counts for the size are used when these are left out
+    #[serde(default)]
+    pub stones: Option<u8>,
+    #[serde(default)]
+    pub caps: Option<u8>,
+    #[serde(default)]
+    pub no_swap: bool,
+}
+
+//the color the seeking player gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SeekColor {
+    #[default]
+    Any,
+    White,
+    Black,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptSeekInput {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveInput {
+    pub game_id: Uuid,
+    //a single ptn ply such as `c3` or `3a1>12`
+    pub ply: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResignInput {
+    pub game_id: Uuid,
+}
+
 #[derive(Debug, Clone)]
 pub struct InputParcel{
     pub client_id: Uuid,
@@ -60,6 +194,45 @@ pub enum Output {
     Joined(JoinedOutput),
     #[serde(rename = "message")]
     Message(MessageOutput),
+    #[serde(rename = "sessionToken")]
+    SessionToken(SessionTokenOutput),
+    #[serde(rename = "passwordChanged")]
+    PasswordChanged,
+    #[serde(rename = "playerInfo")]
+    PlayerInfo(PlayerInfoOutput),
+    #[serde(rename = "seeks")]
+    Seeks(SeeksOutput),
+    #[serde(rename = "seekAdded")]
+    SeekAdded(SeekOutput),
+    #[serde(rename = "seekRemoved")]
+    SeekRemoved(SeekRemovedOutput),
+    #[serde(rename = "gameStarted")]
+    GameStarted(GameStartedOutput),
+    #[serde(rename = "moved")]
+    Moved(MovedOutput),
+    #[serde(rename = "gameOver")]
+    GameOver(GameOverOutput),
+}
+
+impl Output {
+    //the wire name of the output, used to label metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Output::Error(_) => "error",
+            Output::Alive => "alive",
+            Output::Joined(_) => "joined",
+            Output::Message(_) => "message",
+            Output::SessionToken(_) => "sessionToken",
+            Output::PasswordChanged => "passwordChanged",
+            Output::PlayerInfo(_) => "playerInfo",
+            Output::Seeks(_) => "seeks",
+            Output::SeekAdded(_) => "seekAdded",
+            Output::SeekRemoved(_) => "seekRemoved",
+            Output::GameStarted(_) => "gameStarted",
+            Output::Moved(_) => "moved",
+            Output::GameOver(_) => "gameOver",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -80,16 +253,135 @@ impl MessageOutput{
 #[serde(rename_all = "camelCase")]
 pub struct JoinedOutput{
     pub success: bool,
+    pub name: String,
+    pub is_guest: bool,
 }
 
 impl JoinedOutput{
-    pub fn new(success: bool) -> Self {
+    pub fn new(success: bool, name: &str, is_guest: bool) -> Self {
         JoinedOutput{
-            success
+            success,
+            name: String::from(name),
+            is_guest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionTokenOutput{
+    pub token: String,
+    pub expires: i64,
+}
+
+impl SessionTokenOutput{
+    pub fn new(token: String, expires: i64) -> Self {
+        SessionTokenOutput{
+            token,
+            expires
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerInfoOutput{
+    pub name: String,
+    pub is_guest: bool,
+    pub is_bot: bool,
+    pub rating: f64,
+    pub games_played: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub joined: i64,
+    pub last_seen: i64,
+    pub online: bool,
+    pub current_games: Vec<Uuid>,
+}
+
+impl PlayerInfoOutput{
+    pub fn new(player: &Player, online: bool, current_games: Vec<Uuid>) -> Self {
+        PlayerInfoOutput{
+            name: player.name.clone(),
+            is_guest: player.is_guest,
+            is_bot: player.is_bot,
+            rating: player.stats.rating,
+            games_played: player.stats.games_played(),
+            wins: player.stats.wins,
+            losses: player.stats.losses,
+            draws: player.stats.draws,
+            joined: player.joined,
+            last_seen: player.last_seen,
+            online,
+            current_games,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeekOutput{
+    pub id: Uuid,
+    pub player: String,
+    pub size: u8,
+    pub komi: u8,
+    pub time: u32,
+    pub increment: u32,
+    pub color: SeekColor,
+    pub rated: bool,
+    pub stones: u8,
+    pub caps: u8,
+    pub no_swap: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeeksOutput{
+    pub seeks: Vec<SeekOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeekRemovedOutput{
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameStartedOutput{
+    pub game_id: Uuid,
+    pub white: String,
+    pub black: String,
+    pub size: u8,
+    pub komi: u8,
+    pub time: u32,
+    pub increment: u32,
+    pub rated: bool,
+    pub stones: u8,
+    pub caps: u8,
+    pub no_swap: bool,
+}
+
+//clocks are what each player has left, in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedOutput{
+    pub game_id: Uuid,
+    pub ply: String,
+    pub white_time: u64,
+    pub black_time: u64,
+    //the player who moved can win by road on their next move
+    pub tak: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameOverOutput{
+    pub game_id: Uuid,
+    pub result: String,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "code")]
 pub enum OutputError {
@@ -113,16 +405,51 @@ pub enum OutputError {
     LoginOnOtherClient,
     #[serde(rename = "failed-writing-player-to-database")]
     FailedWritingPlayer,
+    #[serde(rename = "rate-limited")]
+    RateLimited,
+    #[serde(rename = "sign-in-locked", rename_all = "camelCase")]
+    SignInLocked { retry_after: u64 },
+    #[serde(rename = "invalid-token")]
+    InvalidToken,
+    #[serde(rename = "already-signed-in")]
+    AlreadySignedIn,
+    #[serde(rename = "server-error")]
+    ServerError,
+    #[serde(rename = "reserved-name")]
+    ReservedName,
+    #[serde(rename = "similar-name-taken")]
+    SimilarNameTaken,
+    #[serde(rename = "not-allowed")]
+    NotAllowed,
+    #[serde(rename = "invalid-seek")]
+    InvalidSeek,
+    #[serde(rename = "seek-not-found")]
+    SeekNotFound,
+    #[serde(rename = "already-in-game")]
+    AlreadyInGame,
+    #[serde(rename = "seek-open")]
+    SeekOpen,
+    #[serde(rename = "game-not-found")]
+    GameNotFound,
+    #[serde(rename = "not-your-turn")]
+    NotYourTurn,
+    #[serde(rename = "illegal-move")]
+    IllegalMove,
 }
 
 #[derive(Debug, Clone)]
 pub struct OutputParcel {
-    pub client_id: Uuid,
+    //none for outputs that go to every signed in player
+    pub client_id: Option<Uuid>,
     pub output: Output,
 }
 
 impl OutputParcel {
     pub fn new(client_id: Uuid, output: Output) -> Self {
-        OutputParcel { client_id, output }
+        OutputParcel { client_id: Some(client_id), output }
+    }
+
+    pub fn to_players(output: Output) -> Self {
+        OutputParcel { client_id: None, output }
     }
 }
\ No newline at end of file
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+use uuid::Uuid;
+use crate::config::{RateLimit, RateLimits, InputLimits};
+use crate::proto::Input;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputKind {
+    Register,
+    SignIn,
+    Post,
+    GuestPost,
+    Lookup,
+    Seek,
+    Play,
+}
+
+impl InputKind {
+    pub fn of(input: &Input, is_guest: bool) -> Self {
+        match input {
+            Input::Register(_) | Input::JoinAsGuest => InputKind::Register,
+            Input::SignIn(_) | Input::SignInWithToken(_) | Input::ChangePassword(_) | Input::ResetPassword(_) => InputKind::SignIn,
+            Input::Post(_) if is_guest => InputKind::GuestPost,
+            Input::Post(_) => InputKind::Post,
+            Input::PlayerInfo(_) | Input::ListSeeks => InputKind::Lookup,
+            Input::Seek(_) | Input::CancelSeek | Input::AcceptSeek(_) => InputKind::Seek,
+            Input::Move(_) | Input::Resign(_) => InputKind::Play,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        let capacity = f64::from(limit.burst);
+        TokenBucket {
+            capacity,
+            tokens: capacity,
+            refill_per_second: capacity / limit.per_seconds.max(1) as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    pub fn try_take(&mut self) -> bool {
+        self.refill();
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    client_buckets: HashMap<(Uuid, InputKind), TokenBucket>,
+    ip_buckets: HashMap<(IpAddr, InputKind), TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            client_buckets: HashMap::new(),
+            ip_buckets: HashMap::new(),
+        }
+    }
+
+    fn limits_for(&self, kind: InputKind) -> InputLimits {
+        match kind {
+            InputKind::Register => self.limits.register,
+            InputKind::SignIn => self.limits.sign_in,
+            InputKind::Post => self.limits.post,
+            InputKind::GuestPost => self.limits.guest_post,
+            InputKind::Lookup => self.limits.lookup,
+            InputKind::Seek => self.limits.seek,
+            InputKind::Play => self.limits.play,
+        }
+    }
+
+    //takes a token from both the client and the ip bucket, returns false if either is empty
+    pub fn check(&mut self, client_id: Uuid, ip: Option<IpAddr>, kind: InputKind) -> bool {
+        let limits = self.limits_for(kind);
+
+        let client_bucket = self.client_buckets
+            .entry((client_id, kind))
+            .or_insert_with(|| TokenBucket::new(limits.client));
+        if !client_bucket.try_take() {
+            return false;
+        }
+
+        if let Some(ip) = ip {
+            let ip_bucket = self.ip_buckets
+                .entry((ip, kind))
+                .or_insert_with(|| TokenBucket::new(limits.ip));
+            if !ip_bucket.try_take() {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn forget_client(&mut self, client_id: Uuid) {
+        self.client_buckets.retain(|(id, _), _| *id != client_id);
+        //ip buckets that have fully refilled carry no state worth keeping
+        self.ip_buckets.retain(|_, bucket| !bucket.is_full());
+    }
+}
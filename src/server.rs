@@ -1,30 +1,200 @@
 use warp::ws::WebSocket;
-use warp::Filter;
-use futures::{future, StreamExt, TryStreamExt};
+use warp::{Filter, Rejection, Reply};
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use serde::Serialize;
+use futures::{future, Future, StreamExt, TryStreamExt};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::broadcast;
-use log::{info,error};
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{field, info, info_span, warn, error, Instrument};
+use tracing::field::display;
 use std::sync::Arc;
 use crate::client::Client;
 use crate::hub::Hub;
 use crate::proto::{InputParcel, Input, OutputParcel};
 use crate::error::Error;
+use crate::config::Config;
+use crate::storage::Storage;
+use crate::auth::AuthHook;
+use crate::metrics::METRICS;
+use crate::api;
+use uuid::Uuid;
+use std::net::SocketAddr;
 
 pub struct ExServer {
-    port: u16,
+    addr: SocketAddr,
     hub: Arc<Hub>,
+    routes: BoxedFilter<(Box<dyn Reply>,)>,
 }
 
-impl ExServer{
-    pub fn new(port: u16) -> Self{
+//assembles an `ExServer`, everything left unset falls back to the defaults used by the standalone binary
+pub struct ServerBuilder {
+    addr: SocketAddr,
+    config: Config,
+    storage: Option<Arc<dyn Storage>>,
+    auth_hook: Option<Arc<dyn AuthHook>>,
+    routes: Option<BoxedFilter<(Box<dyn Reply>,)>>,
+}
+
+impl ServerBuilder {
+    pub fn port(mut self, port: u16) -> Self {
+        self.addr.set_port(port);
+        self
+    }
+
+    //bind port 0 for an ephemeral port, `ServerHandle::addr` reports the one picked
+    pub fn address(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    //replaces the storage the config would open
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn auth_hook(mut self, auth_hook: impl AuthHook + 'static) -> Self {
+        self.auth_hook = Some(Arc::new(auth_hook));
+        self
+    }
+
+    //serves extra warp routes next to the websocket endpoint, may be called more than once
+    pub fn routes<F, R>(mut self, routes: F) -> Self
+    where
+        F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+        R: Reply + 'static,
+    {
+        let routes = routes.map(|reply: R| Box::new(reply) as Box<dyn Reply>).boxed();
+        self.routes = Some(match self.routes.take() {
+            Some(existing) => existing.or(routes).unify().boxed(),
+            None => routes,
+        });
+        self
+    }
+
+    pub fn build(self) -> ExServer {
+        let hub = match self.storage {
+            Some(storage) => Hub::with_storage(&self.config, storage),
+            None => Hub::new(&self.config),
+        };
+        let hub = match self.auth_hook {
+            Some(auth_hook) => hub.with_auth_hook(auth_hook),
+            None => hub,
+        };
+        let routes = self.routes.unwrap_or_else(|| {
+            warp::any()
+                .and_then(|| async { Err::<Box<dyn Reply>, Rejection>(warp::reject::not_found()) })
+                .boxed()
+        });
         ExServer{
-            port,
-            hub: Arc::new(Hub::new()),
+            addr: self.addr,
+            hub: Arc::new(hub),
+            routes,
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct HealthStatus {
+    hub: bool,
+    storage: bool,
+}
+
+//a player signed in on a live connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectedPlayer {
+    pub client_id: Uuid,
+    pub name: String,
+    pub is_guest: bool,
+}
+
+//controls a server started with `ExServer::start`
+pub struct ServerHandle {
+    addr: SocketAddr,
+    hub: Arc<Hub>,
+    shutdown: oneshot::Sender<()>,
+    running: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub async fn connected_players(&self) -> Vec<ConnectedPlayer> {
+        self.hub.connected_players().await.into_iter().map(|(client_id, player)| ConnectedPlayer {
+            client_id,
+            name: player.name,
+            is_guest: player.is_guest,
+        }).collect()
+    }
+
+    //closes every connection, stops accepting new ones and waits for the server to wind down
+    pub async fn shutdown(self) {
+        self.hub.close();
+        let _ = self.shutdown.send(());
+        if let Err(err) = self.running.await {
+            error!("Server stopped with an error: {}", err);
+        }
+    }
+}
+
+impl ExServer{
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder{
+            addr: ([127, 0, 0, 1], 8000).into(),
+            config: Config::default(),
+            storage: None,
+            auth_hook: None,
+            routes: None,
+        }
+    }
+
+    pub fn new(port: u16) -> Self{
+        Self::builder().port(port).build()
+    }
+
+    pub fn with_config(port: u16, config: Config) -> Self{
+        Self::builder().port(port).config(config).build()
+    }
+
+    pub fn with_storage(port: u16, config: Config, storage: Arc<dyn Storage>) -> Self{
+        Self::builder().port(port).config(config).storage(storage).build()
+    }
 
     pub async fn run(&self){
+        let (_, running) = self.serve(self.addr);
+        running.await
+    }
+
+    //runs the server in the background on the current runtime
+    pub fn start(self) -> ServerHandle {
+        let (shutdown, signal) = oneshot::channel();
+        let (addr, running) = self.serve_until(self.addr, async {
+            signal.await.ok();
+        });
+        ServerHandle{
+            addr,
+            hub: self.hub.clone(),
+            shutdown,
+            running: tokio::spawn(running),
+        }
+    }
+
+    //binds the server and returns the bound address with the future running it, bind port 0 for an ephemeral port
+    pub fn serve(&self, addr: SocketAddr) -> (SocketAddr, impl Future<Output = ()> + 'static){
+        self.serve_until(addr, future::pending())
+    }
+
+    fn serve_until(&self, addr: SocketAddr, signal: impl Future<Output = ()> + Send + 'static) -> (SocketAddr, impl Future<Output = ()> + 'static){
         let (input_sender, input_receiver) = mpsc::unbounded_channel::<InputParcel>();
 
         // let feed = warp::path("test").map(|| {});
@@ -32,26 +202,82 @@ impl ExServer{
         let socket = warp::ws()
             .and(warp::any().map(move || input_sender.clone()))
             .and(warp::any().map(move || hub.clone()))
-            .map( move |ws : warp::ws::Ws, input_sender: UnboundedSender<InputParcel>, hub: Arc<Hub>| {
+            .and(warp::addr::remote())
+            .map( move |ws : warp::ws::Ws, input_sender: UnboundedSender<InputParcel>, hub: Arc<Hub>, addr: Option<SocketAddr>| {
             ws.on_upgrade( move |websocket| async move{
-                tokio::spawn(Self::process_client(hub, websocket, input_sender));
+                let client = Client::new(addr.map(|addr| addr.ip()));
+                let span = info_span!("connection", client_id = %client.id, ip = field::Empty);
+                if let Some(ip) = client.ip {
+                    span.record("ip", display(ip));
+                }
+                tokio::spawn(Self::process_client(hub, websocket, input_sender, client).instrument(span));
             })
         });
 
-        let running_hub = self.hub.run(input_receiver);
-        let server = warp::serve(socket).run(([127, 0, 0, 1], self.port));
-        tokio::select! {
-            _ = server => {},
-            _ = running_hub => {},
-        }
+        let hub = self.hub.clone();
+        let metrics = warp::path("metrics")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::any().map(move || hub.clone()))
+            .and_then(Self::render_metrics);
+
+        let hub = self.hub.clone();
+        let health = warp::path("health")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::any().map(move || hub.clone()))
+            .map(|hub: Arc<Hub>| Self::status(&hub, false));
+
+        let hub = self.hub.clone();
+        let ready = warp::path("ready")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(warp::any().map(move || hub.clone()))
+            .map(|hub: Arc<Hub>| Self::status(&hub, true));
+
+        let hub = self.hub.clone();
+        let api = api::routes(self.hub.storage());
+        let routes = socket.or(metrics).or(health).or(ready).or(api).or(self.routes.clone());
+        let (bound, server) = warp::serve(routes)
+            .bind_with_graceful_shutdown(addr, signal);
+        let running = async move {
+            let running_hub = hub.run(input_receiver);
+            tokio::select! {
+                _ = server => {},
+                _ = running_hub => {},
+            }
+        };
+        (bound, running)
     }
 
-    async fn process_client(hub: Arc<Hub>,websocket : WebSocket, input_sender: UnboundedSender<InputParcel>){
+    //`/health` only needs the hub loop alive, `/ready` also needs storage that can take queries
+    fn status(hub: &Hub, require_storage: bool) -> impl Reply {
+        let status = HealthStatus {
+            hub: hub.is_running(),
+            storage: hub.storage_ready(),
+        };
+        let healthy = status.hub && (status.storage || !require_storage);
+        let code = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+        warp::reply::with_status(warp::reply::json(&status), code)
+    }
+
+    async fn render_metrics(hub: Arc<Hub>) -> Result<impl Reply, Rejection> {
+        let body = METRICS.render(hub.gauges().await);
+        Ok(warp::reply::with_header(body, "content-type", "text/plain; version=0.0.4"))
+    }
+
+    //runs inside the connection's span, so everything logged here carries its client id and ip
+    async fn process_client(hub: Arc<Hub>,websocket : WebSocket, input_sender: UnboundedSender<InputParcel>, client: Client){
+        if !hub.on_connect(client).await {
+            //dropping the socket closes the connection
+            info!(reason = "too many connections", "Disconnected");
+            return;
+        }
+        info!("Connected");
+
         let (ws_sink, ws_stream) = websocket.split();
         // let (tx, rx) = mpsc::unbounded_channel();
         // tokio::spawn(rx.forward(wsSink));
-        let client = Client::new();
-        info!("client id: {}", client.id);
 
         let output_receiver: broadcast::Receiver<OutputParcel>  = hub.subscribe();
 
@@ -84,28 +310,56 @@ impl ExServer{
 
         let writing = output_receiver
             .into_stream() //grab broadcast output
-            .try_filter(|out_parcel: &OutputParcel| {
-                future::ready(out_parcel.client_id == client.id)
+            .take_while(|received| {
+                future::ready(!matches!(received, Err(broadcast::RecvError::Closed)))
+            })
+            .filter_map(|received| {
+                future::ready(match received {
+                    Ok(out_parcel) => Some(out_parcel),
+                    //a connection that fell behind misses those outputs but stays open
+                    Err(broadcast::RecvError::Lagged(missed)) => {
+                        warn!(missed, "Output lagged");
+                        METRICS.broadcast_lagged(missed);
+                        None
+                    },
+                    Err(broadcast::RecvError::Closed) => None,
+                })
+            })
+            .filter(|out_parcel: &OutputParcel| {
+                let hub = hub.clone();
+                let target = out_parcel.client_id;
+                async move {
+                    match target {
+                        Some(client_id) => client_id == client.id,
+                        None => hub.is_signed_in(client.id).await,
+                    }
+                }
             }) //filter by client
-            .map_ok( |out_parcel: OutputParcel| {
+            .map( |out_parcel: OutputParcel| {
                 //parse the data from the parcel
                 let data = serde_json::to_string(&out_parcel.output).unwrap();
                 warp::ws::Message::text(data)
-            }).map_err(|err| Error::System(err.to_string()) )//  Error::System(err.to_string()))
-            .try_for_each(|message| async{
+            })
+            .for_each(|message| {
                 //send message to client
                 tx.send(Ok(message)).unwrap();
-                Ok(())
+                future::ready(())
             });
 
-        if let Err(err) = tokio::select! {
-            result = reading => result,
-            result = writing => result,
+        let reason = match tokio::select! {
+            result = reading => result.map(|_| "client closed"),
+            _ = writing => Ok("output closed"),
+            _ = hub.closed() => Ok("server closing"),
         } {
-           error!("Error: {}", err); 
-        }
+            Ok(reason) => reason,
+            Err(err) => {
+                warn!(error = %err, "Connection failed");
+                "error"
+            }
+        };
 
         hub.on_disconnect(client.id).await;
+        info!(reason, "Disconnected");
 
     }
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+use rand::RngCore;
+use uuid::Uuid;
+use tracing::warn;
+
+type HmacSha256 = Hmac<Sha256>;
+
+//a week to pass the token on and use it
+const RESET_TOKEN_LIFETIME: i64 = 7 * 24 * 60 * 60;
+
+//tokens look like `<token id>.<expiry>.<signature>`, only a sha256 of the whole token is stored
+pub struct SessionTokens {
+    secret: Vec<u8>,
+    lifetime: i64,
+}
+
+pub struct IssuedToken {
+    pub token: String,
+    pub hash: String,
+    pub expires: i64,
+}
+
+impl SessionTokens {
+    pub fn new(secret: Option<&str>, lifetime: u64) -> Self {
+        let secret = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                warn!("No token secret configured, session tokens will stop working after a restart");
+                let mut secret = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut secret);
+                secret
+            }
+        };
+        SessionTokens {
+            secret,
+            lifetime: lifetime as i64,
+        }
+    }
+
+    fn sign(&self, payload: &str) -> String {
+        let mut mac = HmacSha256::new_varkey(&self.secret).expect("hmac accepts keys of any length");
+        mac.update(payload.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    pub fn hash(token: &str) -> String {
+        hex::encode(Sha256::digest(token.as_bytes()))
+    }
+
+    pub fn issue(&self, now: i64) -> IssuedToken {
+        let expires = now + self.lifetime;
+        let payload = format!("{}.{}", Uuid::new_v4().to_simple(), expires);
+        let token = format!("{}.{}", payload, self.sign(&payload));
+        IssuedToken {
+            hash: Self::hash(&token),
+            token,
+            expires,
+        }
+    }
+
+    //password resets are issued from the command line, where the server's secret is unknown, so they are plain random tokens
+    pub fn reset_token(now: i64) -> IssuedToken {
+        let token = Uuid::new_v4().to_simple().to_string();
+        IssuedToken {
+            hash: Self::hash(&token),
+            token,
+            expires: now + RESET_TOKEN_LIFETIME,
+        }
+    }
+
+    //checks the signature and expiry, returning the hash to look the session up by
+    pub fn verify(&self, token: &str, now: i64) -> Option<String> {
+        let split = token.rfind('.')?;
+        let (payload, signature) = (&token[..split], &token[split + 1..]);
+        let expires: i64 = payload.split('.').nth(1)?.parse().ok()?;
+
+        let mut mac = HmacSha256::new_varkey(&self.secret).ok()?;
+        mac.update(payload.as_bytes());
+        mac.verify(&hex::decode(signature).ok()?).ok()?;
+
+        if expires <= now {
+            return None;
+        }
+        Some(Self::hash(token))
+    }
+}
@@ -0,0 +1,252 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use tokio::task;
+use uuid::Uuid;
+use crate::error::Error;
+use crate::names::{name_key, name_skeleton};
+use crate::model::game::{GameFilter, GameRecord, RatingEntry};
+use crate::storage::Storage;
+use crate::tak::player::{Player, Stats};
+
+struct Session {
+    player_id: Uuid,
+    expires: i64,
+    revoked: bool,
+}
+
+struct PasswordReset {
+    player_id: Uuid,
+    expires: i64,
+}
+
+struct LoginAttempt {
+    name: String,
+    ip: Option<IpAddr>,
+    success: bool,
+    time: i64,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    players: HashMap<Uuid, Player>,
+    sessions: HashMap<String, Session>,
+    password_resets: HashMap<String, PasswordReset>,
+    login_attempts: Vec<LoginAttempt>,
+    games: Vec<GameRecord>,
+    ratings: Vec<RatingEntry>,
+}
+
+//keeps everything in process memory, for tests and throwaway dev servers
+#[derive(Default)]
+pub struct MemoryStorage {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> Result<MutexGuard<'_, MemoryState>, Error> {
+        self.state.lock().map_err(|_| Error::System(String::from("memory storage poisoned")))
+    }
+
+    fn find_player<'a>(state: &'a MemoryState, user_name: &str) -> Option<&'a Player> {
+        let key = name_key(user_name);
+        state.players.values().find(|player| name_key(&player.name) == key)
+    }
+
+    async fn hash(password: String) -> Result<String, Error> {
+        task::spawn_blocking(move || Player::hash_password(password))
+            .await
+            .map_err(|err| Error::System(err.to_string()))?
+            .map_err(|_| Error::System(String::from("error hashing password")))
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn is_setup(&self) -> bool {
+        true
+    }
+
+    async fn get_user(&self, user_name: &str) -> Result<Option<Player>, Error> {
+        Ok(Self::find_player(&*self.state()?, user_name).cloned())
+    }
+
+    async fn similar_name_exists(&self, user_name: &str) -> Result<bool, Error> {
+        let skeleton = name_skeleton(user_name);
+        Ok(self.state()?.players.values().any(|player| name_skeleton(&player.name) == skeleton))
+    }
+
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<Player>, Error> {
+        Ok(self.state()?.players.get(&id).cloned())
+    }
+
+    async fn write_player(&self, player: Player) -> Result<bool, Error> {
+        let hashed_pass = Self::hash(player.password).await?;
+        //stored players are never bound to a client
+        let mut stored = Player::existing(player.id, player.name, hashed_pass, player.email);
+        stored.joined = player.joined;
+        stored.last_seen = player.joined;
+        stored.password_reset = player.password_reset;
+
+        let mut state = self.state()?;
+        let taken = state.players.values().any(|other| {
+            other.id == stored.id || name_key(&other.name) == name_key(&stored.name) || other.email == stored.email
+        });
+        if taken {
+            return Err(Error::System(String::from("player already exists")));
+        }
+        state.players.insert(stored.id, stored);
+        Ok(true)
+    }
+
+    async fn update_last_seen(&self, player_id: Uuid, time: i64) -> Result<(), Error> {
+        if let Some(player) = self.state()?.players.get_mut(&player_id) {
+            player.last_seen = time;
+        }
+        Ok(())
+    }
+
+    async fn update_password(&self, player_id: Uuid, password: String) -> Result<(), Error> {
+        let hashed_pass = Self::hash(password).await?;
+        if let Some(player) = self.state()?.players.get_mut(&player_id) {
+            player.password = hashed_pass;
+            player.password_reset = false;
+        }
+        Ok(())
+    }
+
+    async fn update_stats(&self, player_id: Uuid, stats: Stats) -> Result<(), Error> {
+        if let Some(player) = self.state()?.players.get_mut(&player_id) {
+            player.stats = stats;
+        }
+        Ok(())
+    }
+
+    async fn leaderboard(&self, limit: u32, offset: u32) -> Result<Vec<Player>, Error> {
+        let mut players: Vec<Player> = self.state()?.players.values()
+            .filter(|player| player.stats.games_played() > 0)
+            .cloned()
+            .collect();
+        players.sort_by(|a, b| b.stats.rating.total_cmp(&a.stats.rating).then_with(|| name_key(&a.name).cmp(&name_key(&b.name))));
+        Ok(players.into_iter().skip(offset as usize).take(limit as usize).collect())
+    }
+
+    async fn write_session(&self, token_hash: &str, player_id: Uuid, expires: i64) -> Result<(), Error> {
+        self.state()?.sessions.insert(String::from(token_hash), Session {
+            player_id,
+            expires,
+            revoked: false,
+        });
+        Ok(())
+    }
+
+    async fn get_session_user(&self, token_hash: &str, now: i64) -> Result<Option<Player>, Error> {
+        let state = self.state()?;
+        Ok(state.sessions.get(token_hash)
+            .filter(|session| session.expires > now && !session.revoked)
+            .and_then(|session| state.players.get(&session.player_id))
+            .cloned())
+    }
+
+    async fn revoke_sessions(&self, player_id: Uuid) -> Result<(), Error> {
+        self.state()?.sessions.values_mut()
+            .filter(|session| session.player_id == player_id)
+            .for_each(|session| session.revoked = true);
+        Ok(())
+    }
+
+    async fn write_password_reset(&self, token_hash: &str, player_id: Uuid, expires: i64) -> Result<(), Error> {
+        self.state()?.password_resets.insert(String::from(token_hash), PasswordReset {
+            player_id,
+            expires,
+        });
+        Ok(())
+    }
+
+    async fn take_password_reset(&self, token_hash: &str, now: i64) -> Result<Option<Uuid>, Error> {
+        Ok(self.state()?.password_resets.remove(token_hash)
+            .filter(|reset| reset.expires > now)
+            .map(|reset| reset.player_id))
+    }
+
+    async fn record_login_attempt(&self, user_name: &str, ip: Option<IpAddr>, success: bool, time: i64) -> Result<(), Error> {
+        self.state()?.login_attempts.push(LoginAttempt {
+            name: name_key(user_name),
+            ip,
+            success,
+            time,
+        });
+        Ok(())
+    }
+
+    async fn account_login_failures(&self, user_name: &str, since: i64) -> Result<(u32, i64), Error> {
+        let name = name_key(user_name);
+        let state = self.state()?;
+        let attempts = state.login_attempts.iter().filter(|attempt| attempt.name == name);
+        let last_success = attempts.clone()
+            .filter(|attempt| attempt.success)
+            .map(|attempt| attempt.time)
+            .max()
+            .unwrap_or(0);
+        let failures: Vec<i64> = attempts
+            .filter(|attempt| !attempt.success && attempt.time > since && attempt.time >= last_success)
+            .map(|attempt| attempt.time)
+            .collect();
+        Ok((failures.len() as u32, failures.into_iter().max().unwrap_or(0)))
+    }
+
+    async fn ip_login_failures(&self, ip: IpAddr, since: i64) -> Result<(u32, i64), Error> {
+        let state = self.state()?;
+        let failures: Vec<i64> = state.login_attempts.iter()
+            .filter(|attempt| attempt.ip == Some(ip) && !attempt.success && attempt.time > since)
+            .map(|attempt| attempt.time)
+            .collect();
+        Ok((failures.len() as u32, failures.into_iter().max().unwrap_or(0)))
+    }
+
+    async fn write_game(&self, game: GameRecord) -> Result<(), Error> {
+        self.state()?.games.push(game);
+        Ok(())
+    }
+
+    async fn get_game(&self, id: Uuid) -> Result<Option<GameRecord>, Error> {
+        Ok(self.state()?.games.iter().find(|game| game.id == id).cloned())
+    }
+
+    async fn list_games(&self, filter: GameFilter) -> Result<Vec<GameRecord>, Error> {
+        let mut games: Vec<GameRecord> = self.state()?.games.iter()
+            .filter(|game| filter.matches(game))
+            .cloned()
+            .collect();
+        games.sort_by_key(|game| std::cmp::Reverse(game.date));
+        Ok(games.into_iter().skip(filter.offset as usize).take(filter.limit as usize).collect())
+    }
+
+    async fn player_games(&self, user_name: &str) -> Result<Vec<GameRecord>, Error> {
+        let mut games: Vec<GameRecord> = self.state()?.games.iter()
+            .filter(|game| game.white == user_name || game.black == user_name)
+            .cloned()
+            .collect();
+        games.sort_by_key(|game| std::cmp::Reverse(game.date));
+        Ok(games)
+    }
+
+    async fn write_rating(&self, entry: RatingEntry) -> Result<(), Error> {
+        self.state()?.ratings.push(entry);
+        Ok(())
+    }
+
+    async fn rating_history(&self, player_id: Uuid) -> Result<Vec<RatingEntry>, Error> {
+        let mut history: Vec<RatingEntry> = self.state()?.ratings.iter()
+            .filter(|entry| entry.player_id == player_id)
+            .cloned()
+            .collect();
+        history.sort_by_key(|entry| entry.time);
+        Ok(history)
+    }
+}
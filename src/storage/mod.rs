@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use std::net::IpAddr;
+use uuid::Uuid;
+use crate::error::Error;
+use crate::model::game::{GameFilter, GameRecord, RatingEntry};
+use crate::clock::unix_time;
+use crate::session::SessionTokens;
+use crate::tak::player::{Player, Stats};
+
+pub mod memory;
+
+pub use memory::MemoryStorage;
+
+//everything the hub persists, implemented by the sqlite `Database` and by `MemoryStorage`
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn is_setup(&self) -> bool;
+
+    //exact lookup on the normalized name key, see `names::name_key`
+    async fn get_user(&self, user_name: &str) -> Result<Option<Player>, Error>;
+    //whether an account exists whose name has the same skeleton, see `names::name_skeleton`
+    async fn similar_name_exists(&self, user_name: &str) -> Result<bool, Error>;
+    async fn get_user_by_id(&self, id: Uuid) -> Result<Option<Player>, Error>;
+    //hashes the player's plain text password before storing it
+    async fn write_player(&self, player: Player) -> Result<bool, Error>;
+    async fn update_last_seen(&self, player_id: Uuid, time: i64) -> Result<(), Error>;
+    async fn update_password(&self, player_id: Uuid, password: String) -> Result<(), Error>;
+    async fn update_stats(&self, player_id: Uuid, stats: Stats) -> Result<(), Error>;
+    //players with at least one finished game, highest rating first
+    async fn leaderboard(&self, limit: u32, offset: u32) -> Result<Vec<Player>, Error>;
+
+    async fn write_session(&self, token_hash: &str, player_id: Uuid, expires: i64) -> Result<(), Error>;
+    //finds the player owning a live, unrevoked session
+    async fn get_session_user(&self, token_hash: &str, now: i64) -> Result<Option<Player>, Error>;
+    async fn revoke_sessions(&self, player_id: Uuid) -> Result<(), Error>;
+
+    async fn write_password_reset(&self, token_hash: &str, player_id: Uuid, expires: i64) -> Result<(), Error>;
+    //removes the token and returns its player when it was still live, tokens only work once
+    async fn take_password_reset(&self, token_hash: &str, now: i64) -> Result<Option<Uuid>, Error>;
+
+    async fn record_login_attempt(&self, user_name: &str, ip: Option<IpAddr>, success: bool, time: i64) -> Result<(), Error>;
+    //failed attempts on an account since `since` and since its last successful sign in, with the time of the latest one
+    async fn account_login_failures(&self, user_name: &str, since: i64) -> Result<(u32, i64), Error>;
+    async fn ip_login_failures(&self, ip: IpAddr, since: i64) -> Result<(u32, i64), Error>;
+
+    async fn write_game(&self, game: GameRecord) -> Result<(), Error>;
+    async fn get_game(&self, id: Uuid) -> Result<Option<GameRecord>, Error>;
+    //newest first
+    async fn list_games(&self, filter: GameFilter) -> Result<Vec<GameRecord>, Error>;
+    //newest first
+    async fn player_games(&self, user_name: &str) -> Result<Vec<GameRecord>, Error>;
+
+    async fn write_rating(&self, entry: RatingEntry) -> Result<(), Error>;
+    //oldest first
+    async fn rating_history(&self, player_id: Uuid) -> Result<Vec<RatingEntry>, Error>;
+}
+
+//hands out a one time token that lets the player set a new password, none when there is no such player
+pub async fn issue_password_reset(storage: &dyn Storage, user_name: &str) -> Result<Option<(String, i64)>, Error> {
+    let player = match storage.get_user(user_name).await? {
+        Some(player) => player,
+        None => return Ok(None),
+    };
+    let issued = SessionTokens::reset_token(unix_time());
+    storage.write_password_reset(&issued.hash, player.id, issued.expires).await?;
+    Ok(Some((issued.token, issued.expires)))
+}
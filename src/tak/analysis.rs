@@ -0,0 +1,49 @@
+use crate::tak::board::{Board, Color, GameResult};
+use crate::tak::moves::{Move, Stone};
+
+//position analysis shared by clients' "Tak!" indicator and the server's bots
+impl Board {
+    //moves that would win on the spot by road if it were `color`'s turn, a move completing both roads counts as it wins for the mover
+    pub fn road_threats(&self, color: Color) -> Vec<Move> {
+        if self.result().is_some() || self.is_opening() {
+            return Vec::new();
+        }
+        let board = self.with_to_move(color);
+        board.road_candidates()
+            .into_iter()
+            .filter(|mv| {
+                let mut after = board.clone();
+                matches!(after.play(mv), Ok(Some(GameResult::Road(winner))) if winner == color)
+            })
+            .collect()
+    }
+
+    //whether the player who just moved threatens to win by road on their next move
+    pub fn is_tak(&self) -> bool {
+        !self.road_threats(self.to_move().opponent()).is_empty()
+    }
+
+    //whether the player to move has to answer a road threat, which is not the case when they can win first
+    pub fn has_forced_response(&self) -> bool {
+        self.is_tak() && self.road_threats(self.to_move()).is_empty()
+    }
+
+    //flats on top of stacks of `color` minus the opponent's, komi left out
+    pub fn flat_difference(&self, color: Color) -> i32 {
+        self.flat_count(color) as i32 - self.flat_count(color.opponent()) as i32
+    }
+
+    //every move that can add road pieces for the player to move, walls never complete a road and a capstone only
+    //needs placing when no stones are left
+    fn road_candidates(&self) -> Vec<Move> {
+        let has_stones = self.reserve(self.to_move()).stones > 0;
+        self.legal_moves()
+            .into_iter()
+            .filter(|mv| match mv {
+                Move::Place { stone: Stone::Wall, .. } => false,
+                Move::Place { stone: Stone::Cap, .. } => !has_stones,
+                _ => true,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+use crate::tak::moves::{Direction, Move, Square, Stone};
+
+pub const MIN_SIZE: u8 = 3;
+pub const MAX_SIZE: u8 = 8;
+//custom piece counts are capped at twice the largest standard counts
+pub const MAX_STONES: u8 = 100;
+pub const MAX_CAPS: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Color::White => 0,
+            Color::Black => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Piece {
+    pub color: Color,
+    pub stone: Stone,
+}
+
+//the pieces a player has not placed yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Reserve {
+    pub stones: u8,
+    pub caps: u8,
+}
+
+//everything that fixes the rules of a game before the first move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GameOptions {
+    pub size: u8,
+    //added to black's flat count when the game is decided on flats, in half points so 1 is half a flat
+    pub half_komi: u8,
+    pub stones: u8,
+    pub caps: u8,
+    //casual variant where the first two plies place the mover's own stones
+    pub no_swap: bool,
+}
+
+impl GameOptions {
+    //the piece counts from the rule book for sizes 3 through 8
+    pub fn standard(size: u8) -> Option<Self> {
+        let (stones, caps) = match size {
+            3 => (10, 0),
+            4 => (15, 0),
+            5 => (21, 1),
+            6 => (30, 1),
+            7 => (40, 2),
+            8 => (50, 2),
+            _ => return None,
+        };
+        Some(GameOptions {
+            size,
+            half_komi: 0,
+            stones,
+            caps,
+            no_swap: false,
+        })
+    }
+
+    //overrides the standard counts, none if they are out of range
+    pub fn with_pieces(mut self, stones: u8, caps: u8) -> Option<Self> {
+        if stones == 0 || stones > MAX_STONES || caps > MAX_CAPS {
+            return None;
+        }
+        self.stones = stones;
+        self.caps = caps;
+        Some(self)
+    }
+
+    pub fn with_komi(mut self, half_komi: u8) -> Self {
+        self.half_komi = half_komi;
+        self
+    }
+
+    pub fn with_no_swap(mut self, no_swap: bool) -> Self {
+        self.no_swap = no_swap;
+        self
+    }
+
+    //komi in flats, as written in ptn headers
+    pub fn komi(&self) -> f64 {
+        self.half_komi as f64 / 2.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameResult {
+    Road(Color),
+    Flats(Color),
+    Draw,
+    //resignation, time or abandonment
+    Default(Color),
+}
+
+impl GameResult {
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            GameResult::Road(color) | GameResult::Flats(color) | GameResult::Default(color) => Some(color),
+            GameResult::Draw => None,
+        }
+    }
+
+    //the ptn result string, the same strings `GameRecord::result` holds
+    pub fn to_ptn(self) -> &'static str {
+        match self {
+            GameResult::Road(Color::White) => "R-0",
+            GameResult::Road(Color::Black) => "0-R",
+            GameResult::Flats(Color::White) => "F-0",
+            GameResult::Flats(Color::Black) => "0-F",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Default(Color::White) => "1-0",
+            GameResult::Default(Color::Black) => "0-1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    GameOver,
+    OffBoard,
+    Occupied,
+    NoPiecesLeft,
+    NotYourStack,
+    CarryLimit,
+    Blocked,
+    OpeningStone,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            MoveError::GameOver => "the game is over",
+            MoveError::OffBoard => "the move leaves the board",
+            MoveError::Occupied => "the square is occupied",
+            MoveError::NoPiecesLeft => "no pieces of that kind are left",
+            MoveError::NotYourStack => "the stack is not controlled by the player to move",
+            MoveError::CarryLimit => "too many pieces are carried",
+            MoveError::Blocked => "a wall or capstone is in the way",
+            MoveError::OpeningStone => "the first two plies place a flat of the opponent",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+//a position and the rules it is played under, stacks are listed bottom piece first
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Board {
+    options: GameOptions,
+    stacks: Vec<Vec<Piece>>,
+    reserves: [Reserve; 2],
+    to_move: Color,
+    ply: u32,
+    result: Option<GameResult>,
+}
+
+impl Board {
+    pub fn new(options: GameOptions) -> Self {
+        let size = options.size as usize;
+        let reserve = Reserve {
+            stones: options.stones,
+            caps: options.caps,
+        };
+        Board {
+            options,
+            stacks: vec![Vec::new(); size * size],
+            reserves: [reserve; 2],
+            to_move: Color::White,
+            ply: 0,
+            result: None,
+        }
+    }
+
+    //a position reached some other way, reserves are what the stacks leave over and none if they would go negative
+    pub(crate) fn from_position(options: GameOptions, stacks: Vec<Vec<Piece>>, to_move: Color, ply: u32) -> Option<Self> {
+        let size = options.size as usize;
+        if stacks.len() != size * size {
+            return None;
+        }
+        let mut reserves = [Reserve { stones: options.stones, caps: options.caps }; 2];
+        for piece in stacks.iter().flatten() {
+            let reserve = &mut reserves[piece.color.index()];
+            let left = if piece.stone == Stone::Cap { &mut reserve.caps } else { &mut reserve.stones };
+            *left = left.checked_sub(1)?;
+        }
+        let mut board = Board {
+            options,
+            stacks,
+            reserves,
+            to_move,
+            ply,
+            result: None,
+        };
+        if ply > 0 {
+            board.result = board.evaluate(to_move.opponent());
+        }
+        Some(board)
+    }
+
+    pub fn options(&self) -> &GameOptions {
+        &self.options
+    }
+
+    pub fn size(&self) -> u8 {
+        self.options.size
+    }
+
+    pub fn to_move(&self) -> Color {
+        self.to_move
+    }
+
+    //plies played so far
+    pub fn ply(&self) -> u32 {
+        self.ply
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    pub fn reserve(&self, color: Color) -> Reserve {
+        self.reserves[color.index()]
+    }
+
+    pub fn stack(&self, square: Square) -> &[Piece] {
+        &self.stacks[self.index(square)]
+    }
+
+    pub fn top(&self, square: Square) -> Option<Piece> {
+        self.stack(square).last().copied()
+    }
+
+    pub fn squares(&self) -> impl Iterator<Item = Square> {
+        let size = self.size();
+        (0..size).flat_map(move |rank| (0..size).map(move |file| Square::new(file, rank)))
+    }
+
+    fn index(&self, square: Square) -> usize {
+        square.rank as usize * self.size() as usize + square.file as usize
+    }
+
+    //whether the next ply is one of the first two, which place an opponent's flat unless the game is played without swap
+    pub fn is_opening(&self) -> bool {
+        self.ply < 2 && !self.options.no_swap
+    }
+
+    //the color of a stone placed by the player to move
+    pub(crate) fn placing_color(&self) -> Color {
+        if self.is_opening() { self.to_move.opponent() } else { self.to_move }
+    }
+
+    fn on_board(&self, square: Square) -> bool {
+        square.file < self.size() && square.rank < self.size()
+    }
+
+    //checks a move without playing it
+    pub fn check(&self, mv: &Move) -> Result<(), MoveError> {
+        if self.result.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if !self.on_board(mv.square()) {
+            return Err(MoveError::OffBoard);
+        }
+        match mv {
+            Move::Place { square, stone } => {
+                if !self.stack(*square).is_empty() {
+                    return Err(MoveError::Occupied);
+                }
+                if self.is_opening() && *stone != Stone::Flat {
+                    return Err(MoveError::OpeningStone);
+                }
+                let reserve = self.reserve(self.placing_color());
+                let left = if *stone == Stone::Cap { reserve.caps } else { reserve.stones };
+                if left == 0 {
+                    return Err(MoveError::NoPiecesLeft);
+                }
+                Ok(())
+            },
+            Move::Spread { square, direction, drops } => {
+                if self.is_opening() {
+                    return Err(MoveError::OpeningStone);
+                }
+                let stack = self.stack(*square);
+                match stack.last() {
+                    Some(top) if top.color == self.to_move => {},
+                    _ => return Err(MoveError::NotYourStack),
+                }
+                let count: usize = drops.iter().map(|drop| *drop as usize).sum();
+                if count == 0 || drops.contains(&0) {
+                    return Err(MoveError::CarryLimit);
+                }
+                if count > self.size() as usize || count > stack.len() {
+                    return Err(MoveError::CarryLimit);
+                }
+                let moving_cap = stack[stack.len() - 1].stone == Stone::Cap;
+
+                let mut target = *square;
+                for (step, drop) in drops.iter().enumerate() {
+                    target = target.step(*direction, self.size()).ok_or(MoveError::OffBoard)?;
+                    match self.top(target).map(|piece| piece.stone) {
+                        Some(Stone::Cap) => return Err(MoveError::Blocked),
+                        //only a capstone dropped alone at the end of a move may flatten a wall
+                        Some(Stone::Wall) if !(moving_cap && *drop == 1 && step == drops.len() - 1) => {
+                            return Err(MoveError::Blocked);
+                        },
+                        _ => {},
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    //plays a checked move and returns the result if it ended the game
+    pub fn play(&mut self, mv: &Move) -> Result<Option<GameResult>, MoveError> {
+        self.check(mv)?;
+        let mover = self.to_move;
+        match mv {
+            Move::Place { square, stone } => {
+                let color = self.placing_color();
+                let reserve = &mut self.reserves[color.index()];
+                if *stone == Stone::Cap {
+                    reserve.caps -= 1;
+                } else {
+                    reserve.stones -= 1;
+                }
+                let index = self.index(*square);
+                self.stacks[index].push(Piece { color, stone: *stone });
+            },
+            Move::Spread { square, direction, drops } => {
+                let count: usize = drops.iter().map(|drop| *drop as usize).sum();
+                let from = self.index(*square);
+                let height = self.stacks[from].len();
+                let mut carried: Vec<Piece> = self.stacks[from].split_off(height - count);
+
+                let mut target = *square;
+                for drop in drops {
+                    target = target.step(*direction, self.size()).ok_or(MoveError::OffBoard)?;
+                    let index = self.index(target);
+                    if let Some(top) = self.stacks[index].last_mut() {
+                        if top.stone == Stone::Wall {
+                            top.stone = Stone::Flat;
+                        }
+                    }
+                    let rest = carried.split_off(*drop as usize);
+                    self.stacks[index].extend(carried);
+                    carried = rest;
+                }
+            }
+        }
+
+        self.ply += 1;
+        self.to_move = mover.opponent();
+        self.result = self.evaluate(mover);
+        Ok(self.result)
+    }
+
+    //the same position with `color` to move, for asking what a side could do if it were their turn
+    pub(crate) fn with_to_move(&self, color: Color) -> Board {
+        let mut board = self.clone();
+        board.to_move = color;
+        board
+    }
+
+    //ends the game without a move, for resignations, flags and abandoned games
+    pub fn end(&mut self, result: GameResult) {
+        self.result = Some(result);
+    }
+
+    //road wins come first and the player who moved wins a double road, then a full board, an empty reserve or a player
+    //left without a legal move goes to flats
+    fn evaluate(&self, mover: Color) -> Option<GameResult> {
+        let mover_road = self.has_road(mover);
+        let other_road = self.has_road(mover.opponent());
+        if mover_road {
+            return Some(GameResult::Road(mover));
+        }
+        if other_road {
+            return Some(GameResult::Road(mover.opponent()));
+        }
+
+        let board_full = self.stacks.iter().all(|stack| !stack.is_empty());
+        let out_of_pieces = self.reserves.iter().any(|reserve| reserve.stones == 0 && reserve.caps == 0);
+        if board_full || out_of_pieces || !self.has_legal_move() {
+            return Some(self.flat_winner());
+        }
+        None
+    }
+
+    //flats on top of a stack count, walls and capstones do not
+    pub fn flat_count(&self, color: Color) -> u32 {
+        self.stacks.iter()
+            .filter_map(|stack| stack.last())
+            .filter(|piece| piece.color == color && piece.stone == Stone::Flat)
+            .count() as u32
+    }
+
+    //komi goes to black, compared in half points so half komi decides what would be a tie
+    pub fn flat_winner(&self) -> GameResult {
+        let white = self.flat_count(Color::White) * 2;
+        let black = self.flat_count(Color::Black) * 2 + self.options.half_komi as u32;
+        if white > black {
+            GameResult::Flats(Color::White)
+        } else if black > white {
+            GameResult::Flats(Color::Black)
+        } else {
+            GameResult::Draw
+        }
+    }
+
+    fn is_road_piece(&self, square: Square, color: Color) -> bool {
+        matches!(self.top(square), Some(piece) if piece.color == color && piece.stone != Stone::Wall)
+    }
+
+    //a chain of flats and capstones joining two opposite edges
+    pub fn has_road(&self, color: Color) -> bool {
+        let size = self.size();
+        let from_left: Vec<Square> = (0..size).map(|rank| Square::new(0, rank)).collect();
+        let from_bottom: Vec<Square> = (0..size).map(|file| Square::new(file, 0)).collect();
+        self.connects(color, &from_left, |square| square.file == size - 1)
+            || self.connects(color, &from_bottom, |square| square.rank == size - 1)
+    }
+
+    fn connects(&self, color: Color, starts: &[Square], is_goal: impl Fn(Square) -> bool) -> bool {
+        let mut seen = vec![false; self.stacks.len()];
+        let mut frontier: Vec<Square> = Vec::new();
+        for square in starts {
+            if self.is_road_piece(*square, color) {
+                seen[self.index(*square)] = true;
+                frontier.push(*square);
+            }
+        }
+        while let Some(square) = frontier.pop() {
+            if is_goal(square) {
+                return true;
+            }
+            for direction in Direction::ALL.iter() {
+                if let Some(next) = square.step(*direction, self.size()) {
+                    let index = self.index(next);
+                    if !seen[index] && self.is_road_piece(next, color) {
+                        seen[index] = true;
+                        frontier.push(next);
+                    }
+                }
+            }
+        }
+        false
+    }
+}
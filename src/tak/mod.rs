@@ -1 +1,9 @@
-pub mod player;
\ No newline at end of file
+pub mod player;
+pub mod ptn;
+pub mod rating;
+pub mod moves;
+pub mod board;
+pub mod analysis;
+pub mod movegen;
+pub mod tps;
+pub mod search;
@@ -0,0 +1,107 @@
+use crate::tak::board::Board;
+use crate::tak::moves::{Direction, Move, Square, Stone};
+
+impl Board {
+    //every legal move for the player to move, placements first, none once the game is over
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        if self.result().is_some() {
+            return moves;
+        }
+        self.push_placements(&mut moves);
+        if !self.is_opening() {
+            for square in self.squares() {
+                if self.top(square).is_some_and(|piece| piece.color == self.to_move()) {
+                    self.push_spreads(square, &mut moves);
+                }
+            }
+        }
+        moves
+    }
+
+    //cheaper than generating every move while there is anything left to place, for ending games where the player to move is stuck
+    pub fn has_legal_move(&self) -> bool {
+        let reserve = self.reserve(self.placing_color());
+        let can_place = reserve.stones > 0 || (reserve.caps > 0 && !self.is_opening());
+        if can_place && self.squares().any(|square| self.stack(square).is_empty()) {
+            return true;
+        }
+        !self.legal_moves().is_empty()
+    }
+
+    fn push_placements(&self, moves: &mut Vec<Move>) {
+        let reserve = self.reserve(self.placing_color());
+        let mut stones = Vec::new();
+        if reserve.stones > 0 {
+            stones.push(Stone::Flat);
+            if !self.is_opening() {
+                stones.push(Stone::Wall);
+            }
+        }
+        if reserve.caps > 0 && !self.is_opening() {
+            stones.push(Stone::Cap);
+        }
+        for square in self.squares().filter(|square| self.stack(*square).is_empty()) {
+            moves.extend(stones.iter().map(|stone| Move::Place { square, stone: *stone }));
+        }
+    }
+
+    //every way of spreading the stack on `square`, stopping short of walls and capstones unless a capstone
+    //alone can flatten the wall
+    pub(crate) fn push_spreads(&self, square: Square, moves: &mut Vec<Move>) {
+        let stack = self.stack(square);
+        let carry = stack.len().min(self.size() as usize) as u8;
+        let moving_cap = stack.last().is_some_and(|piece| piece.stone == Stone::Cap);
+        for direction in Direction::ALL.iter() {
+            let mut reach = 0;
+            let mut flattens = false;
+            let mut next = square.step(*direction, self.size());
+            while let Some(target) = next {
+                match self.top(target).map(|piece| piece.stone) {
+                    Some(Stone::Cap) => break,
+                    Some(Stone::Wall) => {
+                        flattens = moving_cap;
+                        break;
+                    },
+                    _ => {},
+                }
+                reach += 1;
+                next = target.step(*direction, self.size());
+            }
+
+            for count in 1..=carry {
+                for drops in drop_sequences(count, reach) {
+                    moves.push(Move::Spread { square, direction: *direction, drops });
+                }
+                //the capstone goes on alone as the last drop onto the wall
+                if flattens {
+                    for mut drops in drop_sequences(count - 1, reach) {
+                        if drops.len() == reach as usize {
+                            drops.push(1);
+                            moves.push(Move::Spread { square, direction: *direction, drops });
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+//the ways of leaving `count` pieces on at most `reach` squares, at least one on each square passed
+fn drop_sequences(count: u8, reach: u8) -> Vec<Vec<u8>> {
+    if count == 0 {
+        return vec![Vec::new()];
+    }
+    if reach == 0 {
+        return Vec::new();
+    }
+    let mut sequences = vec![vec![count]];
+    for first in 1..count {
+        for rest in drop_sequences(count - first, reach - 1) {
+            let mut sequence = vec![first];
+            sequence.extend(rest);
+            sequences.push(sequence);
+        }
+    }
+    sequences
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use std::str::FromStr;
+
+//a square on the board, file 0 is `a` and rank 0 is `1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Square {
+    pub file: u8,
+    pub rank: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stone {
+    Flat,
+    Wall,
+    Cap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Move {
+    Place { square: Square, stone: Stone },
+    //`drops` holds how many pieces are left on each square along the way, bottom of the carried stack first
+    Spread { square: Square, direction: Direction, drops: Vec<u8> },
+}
+
+impl Square {
+    pub fn new(file: u8, rank: u8) -> Self {
+        Square {
+            file,
+            rank,
+        }
+    }
+
+    //the neighbouring square, none when it would fall off a board of `size`
+    pub fn step(self, direction: Direction, size: u8) -> Option<Square> {
+        let (file, rank) = (self.file as i16, self.rank as i16);
+        let (file, rank) = match direction {
+            Direction::Up => (file, rank + 1),
+            Direction::Down => (file, rank - 1),
+            Direction::Left => (file - 1, rank),
+            Direction::Right => (file + 1, rank),
+        };
+        if file < 0 || rank < 0 || file >= size as i16 || rank >= size as i16 {
+            None
+        } else {
+            Some(Square::new(file as u8, rank as u8))
+        }
+    }
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    fn symbol(self) -> char {
+        match self {
+            Direction::Up => '+',
+            Direction::Down => '-',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+}
+
+impl Move {
+    pub fn square(&self) -> Square {
+        match self {
+            Move::Place { square, .. } => *square,
+            Move::Spread { square, .. } => *square,
+        }
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.file) as char, self.rank + 1)
+    }
+}
+
+//moves print in the shortest ptn form, the same form `ptn::from_server_move` produces
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Move::Place { square, stone } => match stone {
+                Stone::Flat => write!(f, "{}", square),
+                Stone::Wall => write!(f, "S{}", square),
+                Stone::Cap => write!(f, "C{}", square),
+            },
+            Move::Spread { square, direction, drops } => {
+                let count: u8 = drops.iter().sum();
+                if count > 1 {
+                    write!(f, "{}", count)?;
+                }
+                write!(f, "{}{}", square, direction.symbol())?;
+                if drops.len() > 1 {
+                    drops.iter().try_for_each(|drop| write!(f, "{}", drop))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Square {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut chars = text.chars();
+        let file = chars.next().ok_or(())?.to_ascii_lowercase();
+        let rank: u8 = chars.as_str().parse().map_err(|_| ())?;
+        if !('a'..='h').contains(&file) || rank == 0 || rank > 8 {
+            return Err(());
+        }
+        Ok(Square::new(file as u8 - b'a', rank - 1))
+    }
+}
+
+//parses a ptn move, trailing marks such as `*`, `'` or `!` are ignored
+impl FromStr for Move {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim().trim_end_matches(|c| "*'\"!?".contains(c));
+        let count_end = text.find(|c: char| !c.is_ascii_digit()).ok_or(())?;
+        let (count, rest) = text.split_at(count_end);
+        let (stone, rest) = match rest.chars().next() {
+            Some('F') => (Some(Stone::Flat), &rest[1..]),
+            Some('S') => (Some(Stone::Wall), &rest[1..]),
+            Some('C') => (Some(Stone::Cap), &rest[1..]),
+            _ => (None, rest),
+        };
+        //clients send anything, so slice only at char boundaries
+        let square: Square = rest.get(..2).ok_or(())?.parse()?;
+        let rest = &rest[2..];
+
+        let direction = match rest.chars().next() {
+            None => {
+                return if count.is_empty() {
+                    Ok(Move::Place { square, stone: stone.unwrap_or(Stone::Flat) })
+                } else {
+                    Err(())
+                };
+            },
+            Some('+') => Direction::Up,
+            Some('-') => Direction::Down,
+            Some('<') => Direction::Left,
+            Some('>') => Direction::Right,
+            Some(_) => return Err(()),
+        };
+        if stone.is_some() {
+            return Err(());
+        }
+
+        let count: u8 = if count.is_empty() { 1 } else { count.parse().map_err(|_| ())? };
+        let drops: Vec<u8> = rest[1..].chars()
+            .map(|drop| drop.to_digit(10).map(|drop| drop as u8).filter(|drop| *drop > 0).ok_or(()))
+            .collect::<Result<_, _>>()?;
+        let drops = if drops.is_empty() { vec![count] } else { drops };
+        if count == 0 || drops.iter().map(|drop| *drop as u32).sum::<u32>() != count as u32 {
+            return Err(());
+        }
+        Ok(Move::Spread { square, direction, drops })
+    }
+}
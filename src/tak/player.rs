@@ -12,11 +12,44 @@ pub struct Player {
     pub email: String,
 
     pub is_guest: bool,
+    //played by the server, never by a connection
+    pub is_bot: bool,
     is_mod: bool,
     client_id: Option<Uuid>,
 
     reset_token: Option<String>,
-    // stats: Stats
+    pub stats: Stats,
+    pub joined: i64,
+    pub last_seen: i64,
+    //set for imported accounts whose password hash could not be carried over
+    pub password_reset: bool,
+}
+
+pub const DEFAULT_RATING: f64 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub rating: f64,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Stats {
+    pub fn games_played(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            rating: DEFAULT_RATING,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
 }
 
 impl Player{
@@ -28,9 +61,14 @@ impl Player{
             password,
             email,
             is_guest: false,
+            is_bot: false,
             is_mod: false,
             client_id: None,
             reset_token: None,
+            stats: Stats::default(),
+            joined: 0,
+            last_seen: 0,
+            password_reset: false,
         }
     }
 
@@ -41,9 +79,22 @@ impl Player{
             password: String::from(password),
             email: String::from(email),
             is_guest,
+            is_bot: false,
             is_mod: false,
             client_id: Some(client_id),
             reset_token: None,
+            stats: Stats::default(),
+            joined: 0,
+            last_seen: 0,
+            password_reset: false,
+        }
+    }
+
+    //bots sign in like guests, so their games are never rated or stored against an account
+    pub fn bot(name: &str, client_id: Uuid) -> Self {
+        Player {
+            is_bot: true,
+            ..Player::new(name, "", "", client_id, true)
         }
     }
 
@@ -62,4 +113,9 @@ impl Player{
     pub fn get_client(&self) -> Option<Uuid> {
         self.client_id
     }
+
+    //guests can only play unrated games
+    pub fn can_play_rated(&self) -> bool {
+        !self.is_guest
+    }
 }
\ No newline at end of file
@@ -0,0 +1,118 @@
+use crate::clock::utc_date;
+use crate::model::game::GameRecord;
+use crate::tak::board::GameOptions;
+
+//converts one ply of the legacy server notation, e.g. `P A1 C` or `M A1 C1 1 2`, into ptn
+pub fn from_server_move(server_move: &str) -> Option<String> {
+    let parts: Vec<&str> = server_move.split_whitespace().collect();
+    match parts.as_slice() {
+        ["P", square] => Some(square.to_lowercase()),
+        ["P", square, "W"] => Some(format!("S{}", square.to_lowercase())),
+        ["P", square, "C"] => Some(format!("C{}", square.to_lowercase())),
+        ["M", from, to, drops @ ..] if !drops.is_empty() => {
+            let (from_file, from_rank) = square_coordinates(from)?;
+            let (to_file, to_rank) = square_coordinates(to)?;
+            let direction = match (to_file - from_file, to_rank - from_rank) {
+                (0, rank) if rank > 0 => '+',
+                (0, rank) if rank < 0 => '-',
+                (file, 0) if file > 0 => '>',
+                (file, 0) if file < 0 => '<',
+                _ => return None,
+            };
+            let drops: Vec<u32> = drops.iter().map(|drop| drop.parse().ok()).collect::<Option<Vec<u32>>>()?;
+            let count: u32 = drops.iter().sum();
+
+            let mut ptn = String::new();
+            if count > 1 {
+                ptn.push_str(&count.to_string());
+            }
+            ptn.push_str(&from.to_lowercase());
+            ptn.push(direction);
+            if drops.len() > 1 {
+                drops.iter().for_each(|drop| ptn.push_str(&drop.to_string()));
+            }
+            Some(ptn)
+        },
+        _ => None,
+    }
+}
+
+//converts a comma separated legacy move list into space separated ptn plies
+pub fn from_server_notation(notation: &str) -> Option<String> {
+    let plies = notation
+        .split(',')
+        .map(str::trim)
+        .filter(|ply| !ply.is_empty())
+        .map(from_server_move)
+        .collect::<Option<Vec<String>>>()?;
+    Some(plies.join(" "))
+}
+
+fn square_coordinates(square: &str) -> Option<(i32, i32)> {
+    let mut chars = square.chars();
+    let file = chars.next()?.to_ascii_lowercase();
+    let rank: i32 = chars.as_str().parse().ok()?;
+    if !file.is_ascii_lowercase() {
+        return None;
+    }
+    Some((file as i32 - 'a' as i32, rank))
+}
+
+//a file name that sorts by date and is unique per game
+pub fn file_name(game: &GameRecord) -> String {
+    let (year, month, day, _) = utc_date(game.date);
+    let id = game.id.to_simple().to_string();
+    format!("{}-{:02}-{:02} {} vs {} {}.ptn", year, month, day, file_safe(&game.white), file_safe(&game.black), &id[..8])
+}
+
+//imported accounts predate the name rules and may hold quotes or anything else that would break a header
+fn file_safe(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+}
+
+//a complete ptn file for a stored game, tag pairs followed by the numbered move list and the result
+pub fn document(game: &GameRecord) -> String {
+    let (year, month, day, seconds) = utc_date(game.date);
+    let mut ptn = String::new();
+    let mut tag = |name: &str, value: &str| ptn.push_str(&format!("[{} \"{}\"]\n", name, value));
+    tag("Site", "PlayTak.com");
+    tag("Event", game.event.as_deref().unwrap_or("Online Play"));
+    if let Some(round) = &game.round {
+        tag("Round", round);
+    }
+    tag("Date", &format!("{}.{:02}.{:02}", year, month, day));
+    tag("Time", &format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60));
+    tag("Player1", &game.white);
+    tag("Player2", &game.black);
+    tag("Size", &game.size.to_string());
+    tag("Komi", &game.komi.to_string());
+    //readers assume the rule book counts when these are missing
+    if GameOptions::standard(game.size).is_none_or(|standard| standard.stones != game.stones || standard.caps != game.caps) {
+        tag("Flats", &game.stones.to_string());
+        tag("Caps", &game.caps.to_string());
+    }
+    if game.no_swap {
+        tag("Opening", "no-swap");
+    }
+    if game.time > 0 {
+        tag("Clock", &format!("{}:{:02} +{}", game.time / 60, game.time % 60, game.increment));
+    }
+    if game.rated {
+        tag("Rating1", &format!("{:.0}", game.white_rating));
+        tag("Rating2", &format!("{:.0}", game.black_rating));
+    }
+    if !game.result.is_empty() {
+        tag("Result", &game.result);
+    }
+
+    ptn.push('\n');
+    let plies: Vec<&str> = game.notation.split_whitespace().collect();
+    for (number, turn) in plies.chunks(2).enumerate() {
+        ptn.push_str(&format!("{}. {}\n", number + 1, turn.join(" ")));
+    }
+    if !game.result.is_empty() {
+        ptn.push_str(&game.result);
+        ptn.push('\n');
+    }
+    ptn
+}
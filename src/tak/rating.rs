@@ -0,0 +1,10 @@
+pub const K_FACTOR: f64 = 20.0;
+
+pub fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+//plain elo, `score` is 1 for a win, 0.5 for a draw and 0 for a loss
+pub fn updated(rating: f64, opponent: f64, score: f64) -> f64 {
+    rating + K_FACTOR * (score - expected_score(rating, opponent))
+}
@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+use rand::seq::SliceRandom;
+use crate::tak::board::{Board, Color};
+use crate::tak::moves::{Move, Stone};
+
+const WIN: i32 = 1_000_000;
+const FLAT: i32 = 100;
+
+//a small alpha beta search for practice games, deepening one ply at a time until `depth` or the time budget runs out
+pub fn best_move(board: &Board, depth: u32, budget: Duration) -> Option<Move> {
+    let mut moves = board.legal_moves();
+    if moves.is_empty() {
+        return None;
+    }
+    //equal moves are picked at random so games do not repeat
+    moves.shuffle(&mut rand::thread_rng());
+
+    let mut search = Search {
+        deadline: Instant::now() + budget,
+    };
+    let mut best = moves[0].clone();
+    for depth in 1..=depth.max(1) {
+        match search.root(board, &moves, depth) {
+            Some((mv, score)) => {
+                best = mv;
+                if score.abs() >= WIN - 100 {
+                    break;
+                }
+            },
+            None => break,
+        }
+        //search the best move first next time round
+        if let Some(index) = moves.iter().position(|mv| *mv == best) {
+            let mv = moves.remove(index);
+            moves.insert(0, mv);
+        }
+    }
+    Some(best)
+}
+
+struct Search {
+    deadline: Instant,
+}
+
+impl Search {
+    //none when time ran out before every move was searched
+    fn root(&mut self, board: &Board, moves: &[Move], depth: u32) -> Option<(Move, i32)> {
+        let mut best: Option<(Move, i32)> = None;
+        let mut alpha = -WIN - 1;
+        for mv in moves {
+            let mut after = board.clone();
+            if after.play(mv).is_err() {
+                continue;
+            }
+            let score = -self.negamax(&after, depth - 1, -WIN - 1, -alpha)?;
+            if best.as_ref().is_none_or(|(_, best)| score > *best) {
+                best = Some((mv.clone(), score));
+                alpha = alpha.max(score);
+            }
+        }
+        best
+    }
+
+    //the score for the player to move
+    fn negamax(&mut self, board: &Board, depth: u32, mut alpha: i32, beta: i32) -> Option<i32> {
+        if Instant::now() > self.deadline {
+            return None;
+        }
+        if let Some(result) = board.result() {
+            //sooner wins and later losses score better
+            let score = match result.winner() {
+                Some(winner) if winner == board.to_move() => WIN - board.ply() as i32,
+                Some(_) => -WIN + board.ply() as i32,
+                None => 0,
+            };
+            return Some(score);
+        }
+        if depth == 0 {
+            return Some(evaluate(board));
+        }
+
+        let mut best = -WIN - 1;
+        for mv in board.legal_moves() {
+            let mut after = board.clone();
+            if after.play(&mv).is_err() {
+                continue;
+            }
+            let score = -self.negamax(&after, depth - 1, -beta, -alpha)?;
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        Some(best)
+    }
+}
+
+//flats decide most games that do not end in a road, komi included, with a little credit for controlling the board
+fn evaluate(board: &Board) -> i32 {
+    let color = board.to_move();
+    let half_komi = board.options().half_komi as i32;
+    let komi = if color == Color::Black { half_komi } else { -half_komi };
+    let mut score = board.flat_difference(color) * FLAT + komi * FLAT / 2;
+    for square in board.squares() {
+        if let Some(piece) = board.top(square) {
+            let control = match piece.stone {
+                Stone::Flat => 0,
+                Stone::Wall => 10,
+                Stone::Cap => 30,
+            };
+            score += if piece.color == color { control } else { -control };
+        }
+    }
+    score
+}
+
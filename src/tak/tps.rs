@@ -0,0 +1,111 @@
+use crate::tak::board::{Board, Color, GameOptions, Piece};
+use crate::tak::moves::{Square, Stone};
+
+//tak positional system, e.g. `x3,12,2S/x,22S,x2/x4/x4 1 6`, ranks from the top with stacks listed bottom piece first
+impl Board {
+    //none for malformed positions and ones that use more pieces than `options` allow
+    pub fn from_tps(tps: &str, options: GameOptions) -> Option<Board> {
+        let parts: Vec<&str> = tps.split_whitespace().collect();
+        let (rows, player, number) = match parts.as_slice() {
+            [rows, player, number] => (*rows, *player, number.parse::<u32>().ok()?),
+            _ => return None,
+        };
+        let to_move = match player {
+            "1" => Color::White,
+            "2" => Color::Black,
+            _ => return None,
+        };
+        if number == 0 {
+            return None;
+        }
+
+        let size = options.size as usize;
+        let rows: Vec<&str> = rows.split('/').collect();
+        if rows.len() != size {
+            return None;
+        }
+        let mut stacks = vec![Vec::new(); size * size];
+        for (row, squares) in rows.iter().enumerate() {
+            let rank = size - 1 - row;
+            let mut file = 0;
+            for square in squares.split(',') {
+                if let Some(run) = square.strip_prefix('x') {
+                    file += if run.is_empty() { 1 } else { run.parse::<usize>().ok()? };
+                    continue;
+                }
+                if file >= size {
+                    return None;
+                }
+                stacks[rank * size + file] = parse_stack(square)?;
+                file += 1;
+            }
+            if file != size {
+                return None;
+            }
+        }
+
+        let ply = (number - 1) * 2 + if to_move == Color::Black { 1 } else { 0 };
+        Board::from_position(options, stacks, to_move, ply)
+    }
+
+    pub fn to_tps(&self) -> String {
+        let size = self.size();
+        let rows: Vec<String> = (0..size).rev().map(|rank| {
+            let mut squares: Vec<String> = Vec::new();
+            let mut empty = 0;
+            for file in 0..size {
+                let stack = self.stack(Square::new(file, rank));
+                if stack.is_empty() {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    squares.push(empty_run(empty));
+                    empty = 0;
+                }
+                squares.push(stack_tps(stack));
+            }
+            if empty > 0 {
+                squares.push(empty_run(empty));
+            }
+            squares.join(",")
+        }).collect();
+
+        let player = if self.to_move() == Color::White { 1 } else { 2 };
+        format!("{} {} {}", rows.join("/"), player, self.ply() / 2 + 1)
+    }
+}
+
+fn parse_stack(square: &str) -> Option<Vec<Piece>> {
+    let (colors, top) = match square.chars().last()? {
+        'S' => (&square[..square.len() - 1], Stone::Wall),
+        'C' => (&square[..square.len() - 1], Stone::Cap),
+        _ => (square, Stone::Flat),
+    };
+    if colors.is_empty() {
+        return None;
+    }
+    let mut stack: Vec<Piece> = colors.chars().map(|color| match color {
+        '1' => Some(Piece { color: Color::White, stone: Stone::Flat }),
+        '2' => Some(Piece { color: Color::Black, stone: Stone::Flat }),
+        _ => None,
+    }).collect::<Option<_>>()?;
+    if let Some(piece) = stack.last_mut() {
+        piece.stone = top;
+    }
+    Some(stack)
+}
+
+fn stack_tps(stack: &[Piece]) -> String {
+    let mut tps: String = stack.iter().map(|piece| if piece.color == Color::White { '1' } else { '2' }).collect();
+    match stack.last().map(|piece| piece.stone) {
+        Some(Stone::Wall) => tps.push('S'),
+        Some(Stone::Cap) => tps.push('C'),
+        _ => {},
+    }
+    tps
+}
+
+fn empty_run(count: u8) -> String {
+    if count == 1 { String::from("x") } else { format!("x{}", count) }
+}
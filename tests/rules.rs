@@ -0,0 +1,216 @@
+use playtak_ws::tak::board::{Board, Color, GameOptions, GameResult, Piece};
+use playtak_ws::tak::moves::{Direction, Move, Square, Stone};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::HashSet;
+
+fn board(size: u8) -> Board {
+    Board::new(GameOptions::standard(size).expect("a standard size"))
+}
+
+//every move the ptn grammar allows on the board, legal or not
+fn all_moves(size: u8) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for rank in 0..size {
+        for file in 0..size {
+            let square = Square::new(file, rank);
+            for stone in [Stone::Flat, Stone::Wall, Stone::Cap].iter() {
+                moves.push(Move::Place { square, stone: *stone });
+            }
+            for direction in Direction::ALL.iter() {
+                let mut sequences: Vec<Vec<u8>> = (1..=size).map(|drop| vec![drop]).collect();
+                let mut start = 0;
+                while start < sequences.len() {
+                    let end = sequences.len();
+                    for index in start..end {
+                        let sequence = sequences[index].clone();
+                        let carried: u8 = sequence.iter().sum();
+                        if sequence.len() < size as usize {
+                            for drop in 1..=size - carried {
+                                let mut longer = sequence.clone();
+                                longer.push(drop);
+                                sequences.push(longer);
+                            }
+                        }
+                    }
+                    start = end;
+                }
+                moves.extend(sequences.into_iter().map(|drops| Move::Spread { square, direction: *direction, drops }));
+            }
+        }
+    }
+    moves
+}
+
+#[test]
+fn generated_moves_are_exactly_the_legal_ones() {
+    let mut rng = StdRng::seed_from_u64(7);
+    for size in 3..=6 {
+        let candidates = all_moves(size);
+        for _ in 0..10 {
+            let mut board = board(size);
+            while board.result().is_none() {
+                let generated: HashSet<Move> = board.legal_moves().into_iter().collect();
+                let legal: HashSet<Move> = candidates.iter().filter(|mv| board.check(mv).is_ok()).cloned().collect();
+                assert_eq!(generated, legal, "size {} after {} plies", size, board.ply());
+
+                let moves: Vec<Move> = generated.into_iter().collect();
+                //favour spreads so stacks grow tall enough to test the carry limit
+                let spreads: Vec<&Move> = moves.iter().filter(|mv| matches!(mv, Move::Spread { .. })).collect();
+                let mv = if !spreads.is_empty() && rng.gen_bool(0.6) {
+                    spreads.choose(&mut rng).cloned().unwrap().clone()
+                } else {
+                    moves.choose(&mut rng).unwrap().clone()
+                };
+                board.play(&mv).expect("a generated move plays");
+            }
+        }
+    }
+}
+
+#[test]
+fn opening_moves_are_flat_placements() {
+    let board = board(5);
+    let moves = board.legal_moves();
+    assert_eq!(moves.len(), 25);
+    assert!(moves.iter().all(|mv| matches!(mv, Move::Place { stone: Stone::Flat, .. })));
+
+    let no_swap = Board::new(GameOptions::standard(5).unwrap().with_no_swap(true));
+    assert_eq!(no_swap.legal_moves().len(), 25 * 3);
+}
+
+#[test]
+fn moves_parse_from_ptn() {
+    assert_eq!("Cc3".parse::<Move>(), Ok(Move::Place { square: Square::new(2, 2), stone: Stone::Cap }));
+    assert_eq!("3a1>12*".parse::<Move>(),
+        Ok(Move::Spread { square: Square::new(0, 0), direction: Direction::Right, drops: vec![1, 2] }));
+    assert_eq!("a1>21".parse::<Move>(), Err(()));
+    assert_eq!("Sa1+".parse::<Move>(), Err(()));
+}
+
+//input comes straight from clients, none of it may panic
+#[test]
+fn moves_refuse_garbage() {
+    for text in ["aé", "é", "Fé1", "3é+", "a\u{1f600}", "a1>99999999999999999999999999999", "9a1>999999999", "0a1+", ""] {
+        assert_eq!(text.parse::<Move>(), Err(()), "{}", text);
+    }
+}
+
+//leaf counts from the start position at depths 1, 2, 3 and on, as published by the tiltak engine
+const PERFT: &[(u8, &[u64])] = &[
+    (3, &[9, 72, 1_200, 17_792, 271_812, 3_712_952]),
+    (4, &[16, 240, 7_440, 216_464, 6_468_872]),
+    (5, &[25, 600, 43_320, 2_999_784]),
+    (6, &[36, 1_260, 132_720, 13_586_048]),
+    (7, &[49, 2_352, 339_696]),
+    (8, &[64, 4_032, 764_064]),
+];
+
+//finished games have no moves, so they count for nothing below their depth
+fn perft(board: &Board, depth: usize) -> u64 {
+    let moves = board.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves.iter().map(|mv| {
+        let mut after = board.clone();
+        after.play(mv).expect("a generated move plays");
+        perft(&after, depth - 1)
+    }).sum()
+}
+
+fn check_perft(max_leaves: u64) {
+    for (size, counts) in PERFT {
+        for (depth, expected) in counts.iter().enumerate().filter(|(_, count)| **count <= max_leaves) {
+            assert_eq!(perft(&board(*size), depth + 1), *expected, "{}x{} at depth {}", size, size, depth + 1);
+        }
+    }
+}
+
+#[test]
+fn perft_from_the_start() {
+    check_perft(1_000_000);
+}
+
+//too slow for debug builds, run with `cargo test --release -- --ignored`
+#[test]
+#[ignore]
+fn perft_from_the_start_deep() {
+    check_perft(u64::MAX);
+}
+
+fn position(tps: &str) -> Board {
+    let size = tps.split('/').count() as u8;
+    Board::from_tps(tps, GameOptions::standard(size).unwrap()).expect("a valid position")
+}
+
+fn play(board: &mut Board, ply: &str) -> Result<Option<GameResult>, ()> {
+    board.play(&ply.parse().expect("a ptn move")).map_err(|_| ())
+}
+
+#[test]
+fn positions_round_trip() {
+    for tps in vec!["x5/x5/x5/x5/x5 1 1", "x2,12,2S/x,22S,x2/x4/x,1,x,1 2 6", "2,x5/x6/x2,121C,x3/x6/x6/x5,1 1 4"] {
+        assert_eq!(position(tps).to_tps(), tps);
+    }
+    let options = GameOptions::standard(4).unwrap();
+    assert!(Board::from_tps("x4/x4/x4/x3 1 1", options).is_none(), "a short rank");
+    assert!(Board::from_tps("x4/x4/x4/x4 3 1", options).is_none(), "a third player");
+    assert!(Board::from_tps("x4/x4/x4/x3,1C 1 2", options).is_none(), "4x4 has no capstones");
+}
+
+#[test]
+fn capstones_flatten_walls_alone() {
+    let mut board = position("x5/x5/x2,21C,2S,x/x5/x5 1 4");
+    assert!(play(&mut board.clone(), "2c3>").is_err(), "the capstone must arrive alone");
+    assert!(play(&mut board.clone(), "2c3>11").is_err(), "a flat cannot go onto the wall");
+    play(&mut board, "c3>").expect("the capstone flattens the wall");
+    assert_eq!(board.stack("d3".parse().unwrap()),
+        &[Piece { color: Color::Black, stone: Stone::Flat }, Piece { color: Color::White, stone: Stone::Cap }][..]);
+
+    let mut reaching = position("x5/x5/x2,21C,x,2S/x5/x5 1 4");
+    play(&mut reaching, "2c3>11").expect("the capstone flattens the wall at the end of the spread");
+    assert_eq!(reaching.top("e3".parse().unwrap()), Some(Piece { color: Color::White, stone: Stone::Cap }));
+    assert_eq!(reaching.stack("e3".parse().unwrap())[0].stone, Stone::Flat);
+
+    assert!(play(&mut position("x5/x5/x2,1,2S,x/x5/x5 1 4"), "c3>").is_err(), "flats do not flatten walls");
+    assert!(play(&mut position("x5/x5/x2,1C,2C,x/x5/x5 1 4"), "c3>").is_err(), "nothing goes onto a capstone");
+}
+
+#[test]
+fn roads_follow_the_tops_of_stacks() {
+    //black lifting its flat off c1 uncovers white's road, which wins for white even though black moved
+    let mut board = position("x4/x4/x4/1,1,12,1 2 5");
+    assert_eq!(board.result(), None);
+    assert_eq!(play(&mut board, "c1+"), Ok(Some(GameResult::Road(Color::White))));
+
+    //walls block roads and capstones complete them
+    let mut blocked = position("x4/x4/x4/1,1,1S,1 1 5");
+    assert!(!blocked.has_road(Color::White));
+    play(&mut blocked, "a4").unwrap();
+    assert_eq!(blocked.result(), None);
+    let mut capped = position("x5/x5/x5/x5/1,1,x,1,1 1 5");
+    assert_eq!(play(&mut capped, "Cc1"), Ok(Some(GameResult::Road(Color::White))));
+}
+
+#[test]
+fn the_mover_wins_simultaneous_roads() {
+    //spreading d1 finishes black's road on the second rank and uncovers white's on the first
+    let mut board = position("x4/x4/2,2,2,x/1,1,1,12 2 5");
+    assert_eq!(play(&mut board, "d1+"), Ok(Some(GameResult::Road(Color::Black))));
+    assert!(board.has_road(Color::White) && board.has_road(Color::Black));
+}
+
+#[test]
+fn analysis_finds_threats_and_forced_responses() {
+    let board = position("x4/x4/2,2,x2/1,1,1,x 1 4");
+    assert_eq!(board.road_threats(Color::White), vec!["d1".parse::<Move>().unwrap()]);
+    //white to move wins at once, so nothing is forced
+    assert!(!board.has_forced_response());
+
+    let board = position("x4/x4/2,2,x2/1,1,1,x 2 4");
+    assert!(board.is_tak());
+    assert!(board.has_forced_response());
+    assert_eq!(board.flat_difference(Color::White), 1);
+}
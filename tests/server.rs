@@ -0,0 +1,617 @@
+use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
+use playtak_ws::auth::AuthHook;
+use playtak_ws::config::{BotConfig, Config, EngineConfig, InputLimits, RateLimit, RateLimits, StorageKind};
+use playtak_ws::model::game::{GameRecord, RatingEntry};
+use playtak_ws::server::{ConnectedPlayer, ExServer, ServerHandle};
+use playtak_ws::storage::{issue_password_reset, MemoryStorage, Storage};
+use playtak_ws::tak::player::{Player, Stats};
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use uuid::Uuid;
+use warp::Filter;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn memory_config() -> Config {
+    let mut config = Config::default();
+    config.storage = StorageKind::Memory;
+    config
+}
+
+//starts a server on an ephemeral port with memory storage, it runs until the test's runtime shuts down
+fn start_server() -> SocketAddr {
+    let server = ExServer::with_config(0, memory_config());
+    let (addr, running) = server.serve(([127, 0, 0, 1], 0).into());
+    tokio::spawn(running);
+    addr
+}
+
+struct TestClient {
+    socket: WebSocketStream<TcpStream>,
+}
+
+impl TestClient {
+    async fn connect(addr: SocketAddr) -> Self {
+        let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .expect("connecting to the server");
+        TestClient {
+            socket,
+        }
+    }
+
+    async fn send(&mut self, input: Value) {
+        self.socket.send(Message::text(input.to_string())).await.expect("sending input");
+    }
+
+    async fn receive(&mut self) -> Value {
+        let message = time::timeout(TIMEOUT, self.socket.next())
+            .await
+            .expect("timed out waiting for output")
+            .expect("connection closed")
+            .expect("reading output");
+        serde_json::from_str(message.to_text().expect("text output")).expect("json output")
+    }
+
+    async fn request(&mut self, input: Value) -> Value {
+        self.send(input).await;
+        self.receive().await
+    }
+
+    //skips broadcasts meant for everyone until an output of this type arrives
+    async fn receive_type(&mut self, output_type: &str) -> Value {
+        loop {
+            let output = self.receive().await;
+            if output["type"] == output_type {
+                return output;
+            }
+        }
+    }
+
+    async fn close(mut self) {
+        self.socket.close(None).await.expect("closing the connection");
+    }
+}
+
+fn register(name: &str) -> Value {
+    json!({"type": "register", "payload": {"name": name, "password": "correct horse", "email": format!("{}@example.com", name)}})
+}
+
+fn sign_in(name: &str) -> Value {
+    json!({"type": "signIn", "payload": {"name": name, "password": "correct horse"}})
+}
+
+fn joined(name: &str, is_guest: bool) -> Value {
+    json!({"type": "joined", "payload": {"success": true, "name": name, "isGuest": is_guest}})
+}
+
+//a bare http/1.1 request, returns the raw response
+async fn http_get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.expect("connecting for http");
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.expect("writing request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.expect("reading response");
+    response
+}
+
+//disconnects are processed after the socket closes, wait until the hub has let the player go
+async fn wait_until_offline(client: &mut TestClient, name: &str) {
+    for _ in 0..50 {
+        let info = client.request(json!({"type": "playerInfo", "payload": {"name": name}})).await;
+        if info["payload"]["online"] == json!(false) {
+            return;
+        }
+        time::delay_for(Duration::from_millis(100)).await;
+    }
+    panic!("{} never went offline", name);
+}
+
+#[tokio::test]
+async fn guests_join_and_post() {
+    let addr = start_server();
+    let mut client = TestClient::connect(addr).await;
+
+    assert_eq!(client.request(json!({"type": "post", "payload": {"body": "hello"}})).await,
+        json!({"type": "error", "payload": {"code": "not-joined"}}));
+    assert_eq!(client.request(json!({"type": "joinAsGuest"})).await, joined("Guest1", true));
+    assert_eq!(client.request(json!({"type": "post", "payload": {"body": "hello"}})).await,
+        json!({"type": "message", "payload": {"message": "hello"}}));
+}
+
+#[tokio::test]
+async fn register_then_sign_in_again() {
+    let addr = start_server();
+
+    let mut first = TestClient::connect(addr).await;
+    assert_eq!(first.request(register("Alice")).await, joined("Alice", false));
+    let session = first.receive().await;
+    assert_eq!(session["type"], "sessionToken");
+    let token = session["payload"]["token"].as_str().expect("token").to_string();
+    first.close().await;
+
+    let mut second = TestClient::connect(addr).await;
+    wait_until_offline(&mut second, "Alice").await;
+    assert_eq!(second.request(sign_in("Alice")).await, joined("Alice", false));
+    assert_eq!(second.receive().await["type"], "sessionToken");
+    assert_eq!(second.request(json!({"type": "post", "payload": {"body": "back again"}})).await,
+        json!({"type": "message", "payload": {"message": "back again"}}));
+    second.close().await;
+
+    let mut third = TestClient::connect(addr).await;
+    wait_until_offline(&mut third, "Alice").await;
+    assert_eq!(third.request(json!({"type": "signInWithToken", "payload": {"token": token}})).await, joined("Alice", false));
+}
+
+#[tokio::test]
+async fn clients_only_see_their_own_outputs() {
+    let addr = start_server();
+    let mut first = TestClient::connect(addr).await;
+    let mut second = TestClient::connect(addr).await;
+
+    assert_eq!(first.request(json!({"type": "joinAsGuest"})).await, joined("Guest1", true));
+    assert_eq!(second.request(json!({"type": "signIn", "payload": {"name": "Nobody", "password": "x"}})).await,
+        json!({"type": "error", "payload": {"code": "player-not-found"}}));
+}
+
+#[tokio::test]
+async fn a_crowded_lobby_stays_connected() {
+    let config = Config {
+        max_connections_per_ip: 32,
+        rate_limits: RateLimits {
+            register: InputLimits::new(RateLimit::new(3, 60), RateLimit::new(32, 600)),
+            ..RateLimits::default()
+        },
+        ..memory_config()
+    };
+    let server = ExServer::with_config(0, config);
+    let (addr, running) = server.serve(([127, 0, 0, 1], 0).into());
+    tokio::spawn(running);
+
+    let mut guests = Vec::new();
+    for _ in 0..20 {
+        let mut guest = TestClient::connect(addr).await;
+        guest.request(json!({"type": "joinAsGuest"})).await;
+        guests.push(guest);
+    }
+    let mut seeker = TestClient::connect(addr).await;
+    seeker.request(json!({"type": "joinAsGuest"})).await;
+    seeker.send(json!({"type": "seek", "payload": {"size": 5, "time": 600, "increment": 5, "komi": 0, "color": "any"}})).await;
+
+    //more lobby outputs than the channel holds, nobody may be dropped for it
+    for guest in guests.iter_mut() {
+        guest.receive_type("seekAdded").await;
+        let info = guest.request(json!({"type": "playerInfo", "payload": {"name": "Guest1"}})).await;
+        assert_eq!(info["payload"]["online"], json!(true));
+    }
+}
+
+//only admits the names on its list, like a tournament would
+struct Entrants(Vec<&'static str>);
+
+#[async_trait]
+impl AuthHook for Entrants {
+    async fn allow_register(&self, name: &str, _email: &str, _ip: Option<IpAddr>) -> bool {
+        self.0.contains(&name)
+    }
+
+    async fn allow_sign_in(&self, player: &Player, _ip: Option<IpAddr>) -> bool {
+        self.0.contains(&player.name.as_str())
+    }
+}
+
+#[tokio::test]
+async fn embedded_server_with_hooks_and_routes() {
+    let handle = ExServer::builder()
+        .address(([127, 0, 0, 1], 0).into())
+        .config(memory_config())
+        .auth_hook(Entrants(vec!["Alice"]))
+        .routes(warp::path("tournament").map(|| "round 1"))
+        .build()
+        .start();
+    let addr = handle.addr();
+
+    let mut alice = TestClient::connect(addr).await;
+    assert_eq!(alice.request(register("Alice")).await, joined("Alice", false));
+    alice.receive().await;
+
+    let mut bob = TestClient::connect(addr).await;
+    assert_eq!(bob.request(register("Bobby")).await, json!({"type": "error", "payload": {"code": "not-allowed"}}));
+    assert_eq!(bob.request(json!({"type": "joinAsGuest"})).await, json!({"type": "error", "payload": {"code": "not-allowed"}}));
+
+    let players = handle.connected_players().await;
+    assert_eq!(players.len(), 1);
+    assert_eq!(players[0], ConnectedPlayer { client_id: players[0].client_id, name: String::from("Alice"), is_guest: false });
+
+    let response = http_get(addr, "/tournament").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("round 1"), "{}", response);
+
+    time::timeout(TIMEOUT, handle.shutdown()).await.expect("shutting down");
+    let closed = time::timeout(TIMEOUT, alice.socket.next()).await.expect("connection left open");
+    assert!(!matches!(closed, Some(Ok(Message::Text(_)))));
+}
+
+//admits guests while the server has room, looking up who is online as it decides
+struct Capacity {
+    server: Arc<tokio::sync::Mutex<Option<ServerHandle>>>,
+    max_players: usize,
+}
+
+#[async_trait]
+impl AuthHook for Capacity {
+    async fn allow_register(&self, _name: &str, _email: &str, _ip: Option<IpAddr>) -> bool {
+        true
+    }
+
+    async fn allow_sign_in(&self, _player: &Player, _ip: Option<IpAddr>) -> bool {
+        match self.server.lock().await.as_ref() {
+            Some(server) => server.connected_players().await.len() < self.max_players,
+            None => false,
+        }
+    }
+}
+
+#[tokio::test]
+async fn hooks_can_look_up_connected_players() {
+    let server = Arc::new(tokio::sync::Mutex::new(None));
+    let handle = ExServer::builder()
+        .address(([127, 0, 0, 1], 0).into())
+        .config(memory_config())
+        .auth_hook(Capacity { server: server.clone(), max_players: 1 })
+        .build()
+        .start();
+    let addr = handle.addr();
+    *server.lock().await = Some(handle);
+
+    let mut first = TestClient::connect(addr).await;
+    assert_eq!(first.request(json!({"type": "joinAsGuest"})).await, joined("Guest1", true));
+    let mut second = TestClient::connect(addr).await;
+    assert_eq!(second.request(json!({"type": "joinAsGuest"})).await, json!({"type": "error", "payload": {"code": "not-allowed"}}));
+
+    let handle = server.lock().await.take().expect("server handle");
+    time::timeout(TIMEOUT, handle.shutdown()).await.expect("shutting down");
+}
+
+#[tokio::test]
+async fn flagged_accounts_reset_their_password() {
+    let storage = Arc::new(MemoryStorage::new());
+    let mut imported = Player::existing(Uuid::new_v4(), String::from("Oldtimer"), String::new(), String::from("old@example.com"));
+    imported.password_reset = true;
+    storage.write_player(imported).await.expect("writing player");
+    let server = ExServer::with_storage(0, memory_config(), storage.clone());
+    let (addr, running) = server.serve(([127, 0, 0, 1], 0).into());
+    tokio::spawn(running);
+    let mut client = TestClient::connect(addr).await;
+
+    //a flagged account looks like any account given the wrong password
+    let invalid_password = json!({"type": "error", "payload": {"code": "invalid-password"}});
+    assert_eq!(client.request(sign_in("Oldtimer")).await, invalid_password);
+    assert_eq!(client.request(json!({"type": "signIn", "payload": {"name": "Oldtimer", "password": ""}})).await, invalid_password);
+
+    let (token, _) = issue_password_reset(storage.as_ref(), "Oldtimer").await.expect("issuing token").expect("player exists");
+    let reset = json!({"type": "resetPassword", "payload": {"token": token, "newPassword": "correct horse"}});
+    assert_eq!(client.request(reset.clone()).await, json!({"type": "passwordChanged"}));
+    assert_eq!(client.request(reset).await, json!({"type": "error", "payload": {"code": "invalid-token"}}));
+    assert_eq!(client.request(sign_in("Oldtimer")).await, joined("Oldtimer", false));
+}
+
+#[tokio::test]
+async fn metrics_count_clients_and_inputs() {
+    let addr = start_server();
+    let mut guest = TestClient::connect(addr).await;
+    guest.request(json!({"type": "joinAsGuest"})).await;
+    guest.request(json!({"type": "post", "payload": {"body": ""}})).await;
+
+    let response = http_get(addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains("\nplaytak_connected_clients 1\n"), "{}", response);
+    assert!(response.contains("\nplaytak_guests 1\n"), "{}", response);
+    assert!(response.contains("\nplaytak_signed_in_players 0\n"), "{}", response);
+    //counters are shared by every server in the process, so only check they are there
+    assert!(response.contains("playtak_inputs_total{type=\"joinAsGuest\"}"), "{}", response);
+    assert!(response.contains("playtak_errors_total{code=\"invalid-message-body\"}"), "{}", response);
+}
+
+#[tokio::test]
+async fn health_and_readiness() {
+    let addr = start_server();
+    let health = http_get(addr, "/health").await;
+    assert!(health.starts_with("HTTP/1.1 200"), "{}", health);
+    assert!(health.ends_with(r#"{"hub":true,"storage":true}"#), "{}", health);
+    let ready = http_get(addr, "/ready").await;
+    assert!(ready.starts_with("HTTP/1.1 200"), "{}", ready);
+}
+
+#[tokio::test]
+async fn not_ready_without_a_database() {
+    let mut config = Config::default();
+    config.database_path = String::from("/nonexistent/playtak_data.db");
+    let handle = ExServer::builder().address(([127, 0, 0, 1], 0).into()).config(config).build().start();
+
+    let health = http_get(handle.addr(), "/health").await;
+    assert!(health.starts_with("HTTP/1.1 200"), "{}", health);
+    let ready = http_get(handle.addr(), "/ready").await;
+    assert!(ready.starts_with("HTTP/1.1 503"), "{}", ready);
+    assert!(ready.ends_with(r#"{"hub":true,"storage":false}"#), "{}", ready);
+}
+
+fn game(white: &str, black: &str, date: i64, size: u8, result: &str) -> GameRecord {
+    GameRecord {
+        id: Uuid::new_v4(),
+        date,
+        size,
+        white: String::from(white),
+        black: String::from(black),
+        notation: String::from("a1 e5 Cc3 1a1+"),
+        result: String::from(result),
+        rated: true,
+        white_rating: 1000.0,
+        black_rating: 1000.0,
+        time: 600,
+        increment: 20,
+        komi: 0.0,
+        stones: 21,
+        caps: 1,
+        no_swap: false,
+        event: None,
+        round: None,
+    }
+}
+
+//the body of a response, with the status line checked
+fn json_body(response: &str, status: &str) -> Value {
+    assert!(response.starts_with(&format!("HTTP/1.1 {}", status)), "{}", response);
+    let body = &response[response.find("\r\n\r\n").expect("end of headers") + 4..];
+    serde_json::from_str(body).expect("json body")
+}
+
+#[tokio::test]
+async fn api_serves_games_and_players() {
+    let storage = Arc::new(MemoryStorage::new());
+    let winner = Player::new("Alice", "correct horse", "alice@example.com", Uuid::nil(), false);
+    let winner_id = winner.id;
+    storage.write_player(winner).await.expect("writing player");
+    storage.update_stats(winner_id, Stats { rating: 1010.0, wins: 1, losses: 0, draws: 0 }).await.expect("writing stats");
+    let first = game("Alice", "Bobby", 1_600_000_000, 5, "R-0");
+    let first_id = first.id;
+    storage.write_game(first).await.expect("writing game");
+    storage.write_game(game("Bobby", "Carol", 1_600_000_100, 6, "0-F")).await.expect("writing game");
+    storage.write_rating(RatingEntry { player_id: winner_id, game_id: Some(first_id), rating: 1010.0, time: 1_600_000_000 })
+        .await.expect("writing rating");
+
+    let handle = ExServer::builder()
+        .address(([127, 0, 0, 1], 0).into())
+        .config(memory_config())
+        .storage(storage)
+        .build()
+        .start();
+    let addr = handle.addr();
+
+    let games = json_body(&http_get(addr, "/api/games").await, "200");
+    assert_eq!(games["games"].as_array().expect("games").len(), 2);
+    assert_eq!(games["games"][0]["white"], "Bobby");
+    let alices = json_body(&http_get(addr, "/api/games?player=Alice&limit=1").await, "200");
+    assert_eq!(alices["games"].as_array().expect("games").len(), 1);
+    assert_eq!(alices["limit"], 1);
+    //players are found however their name is cased, like profiles
+    let bobbys = json_body(&http_get(addr, "/api/games?player=bobby").await, "200");
+    assert_eq!(bobbys["games"].as_array().expect("games").len(), 2);
+    let sixes = json_body(&http_get(addr, "/api/games?size=6&offset=1").await, "200");
+    assert!(sixes["games"].as_array().expect("games").is_empty());
+
+    let detail = json_body(&http_get(addr, &format!("/api/games/{}", first_id)).await, "200");
+    assert_eq!(detail["moves"], json!(["a1", "e5", "Cc3", "1a1+"]));
+    let ptn = detail["ptn"].as_str().expect("ptn");
+    assert!(ptn.contains("[Date \"2020.09.13\"]"), "{}", ptn);
+    assert!(ptn.contains("[Clock \"10:00 +20\"]"), "{}", ptn);
+    assert!(ptn.contains("\n1. a1 e5\n2. Cc3 1a1+\nR-0\n"), "{}", ptn);
+    json_body(&http_get(addr, &format!("/api/games/{}", Uuid::new_v4())).await, "404");
+
+    let profile = json_body(&http_get(addr, "/api/players/alice").await, "200");
+    assert_eq!(profile["name"], "Alice");
+    assert_eq!(profile["gamesPlayed"], 1);
+    assert_eq!(profile["ratingHistory"][0]["gameId"], json!(first_id));
+    assert_eq!(json_body(&http_get(addr, "/api/players/Nobody").await, "404"), json!({"error": "player-not-found"}));
+
+    let leaderboard = json_body(&http_get(addr, "/api/leaderboard").await, "200");
+    assert_eq!(leaderboard, json!([{"rank": 1, "name": "Alice", "rating": 1010.0, "gamesPlayed": 1}]));
+}
+
+#[tokio::test]
+async fn games_download_as_ptn() {
+    let storage = Arc::new(MemoryStorage::new());
+    let mut final_game = game("Alice", "Bobby", 1_600_000_000, 6, "F-0");
+    final_game.komi = 2.5;
+    final_game.event = Some(String::from("Spring Open"));
+    final_game.round = Some(String::from("3"));
+    let final_id = final_game.id;
+    storage.write_game(final_game).await.expect("writing game");
+    storage.write_game(game("Carol", "Bobby", 1_600_100_000, 5, "0-R")).await.expect("writing game");
+    storage.write_game(game("Carol", "Dave", 1_600_200_000, 5, "1/2-1/2")).await.expect("writing game");
+    let legacy = game("Old \"Tom\"; x", "Dave", 1_599_000_000, 5, "R-0");
+    let legacy_id = legacy.id;
+    storage.write_game(legacy).await.expect("writing game");
+    let handle = ExServer::builder()
+        .address(([127, 0, 0, 1], 0).into())
+        .config(memory_config())
+        .storage(storage)
+        .build()
+        .start();
+    let addr = handle.addr();
+
+    let download = http_get(addr, &format!("/api/games/{}/ptn", final_id)).await;
+    assert!(download.starts_with("HTTP/1.1 200"), "{}", download);
+    assert!(download.contains("content-disposition: attachment; filename=\"2020-09-13 Alice vs Bobby"), "{}", download);
+    for tag in &["[Event \"Spring Open\"]", "[Round \"3\"]", "[Komi \"2.5\"]", "[Player1 \"Alice\"]", "[Rating2 \"1000\"]", "[Result \"F-0\"]"] {
+        assert!(download.contains(tag), "missing {} in {}", tag, download);
+    }
+
+    //names from before the registration rules must not break out of the header
+    let download = http_get(addr, &format!("/api/games/{}/ptn", legacy_id)).await;
+    assert!(download.contains("filename=\"2020-09-01 Old__Tom___x vs Dave"), "{}", download);
+
+    let stream = http_get(addr, "/api/export?player=BOBBY").await;
+    assert!(stream.starts_with("HTTP/1.1 200"), "{}", stream);
+    assert_eq!(stream.matches("[Site \"PlayTak.com\"]").count(), 2, "{}", stream);
+    json_body(&http_get(addr, "/api/export").await, "400");
+
+    let mut request = TcpStream::connect(addr).await.expect("connecting for http");
+    request.write_all(b"GET /api/export?after=1600050000&format=zip HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.expect("writing request");
+    let mut response = Vec::new();
+    request.read_to_end(&mut response).await.expect("reading response");
+    let body_start = response.windows(4).position(|window| window == b"\r\n\r\n").expect("end of headers") + 4;
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(response[body_start..].to_vec())).expect("zip body");
+    assert_eq!(archive.len(), 2);
+}
+
+#[tokio::test]
+async fn rated_games_are_stored_with_komi() {
+    let addr = start_server();
+    let mut white = TestClient::connect(addr).await;
+    let mut black = TestClient::connect(addr).await;
+    white.request(register("Walter")).await;
+    black.request(register("Blanca")).await;
+
+    white.send(json!({"type": "seek", "payload": {"size": 5, "time": 600, "increment": 5, "komi": 3, "color": "white", "rated": true}})).await;
+    let seek = white.receive_type("seekAdded").await;
+    assert_eq!(seek["payload"]["komi"], 3);
+    black.send(json!({"type": "acceptSeek", "payload": {"id": seek["payload"]["id"]}})).await;
+    let started = black.receive_type("gameStarted").await;
+    assert_eq!(started["payload"]["white"], "Walter");
+    let game_id = started["payload"]["gameId"].as_str().expect("game id").to_string();
+    assert_eq!(white.receive_type("gameStarted").await, started);
+
+    white.send(json!({"type": "move", "payload": {"gameId": game_id, "ply": "c3"}})).await;
+    assert_eq!(black.receive_type("moved").await["payload"]["ply"], "c3");
+    black.send(json!({"type": "resign", "payload": {"gameId": game_id}})).await;
+    assert_eq!(white.receive_type("gameOver").await["payload"]["result"], "1-0");
+
+    let game = json_body(&http_get(addr, &format!("/api/games/{}", game_id)).await, "200");
+    assert_eq!(game["komi"], 1.5);
+    assert_eq!(game["moves"], json!(["c3"]));
+    let player = json_body(&http_get(addr, "/api/players/Walter").await, "200");
+    assert_eq!(player["wins"], 1);
+    assert!(player["rating"].as_f64().expect("rating") > 1000.0);
+}
+
+//joins as a guest and takes the bot's seek once it shows up, returns the game id and whether the guest is white
+async fn accept_bot_seek(client: &mut TestClient, bot: &str) -> (String, bool) {
+    assert_eq!(client.request(json!({"type": "joinAsGuest"})).await, joined("Guest1", true));
+    //the bot posts its seek once the hub is running
+    let mut seek = Value::Null;
+    for _ in 0..50 {
+        client.send(json!({"type": "listSeeks"})).await;
+        let seeks = client.receive_type("seeks").await;
+        if let Some(found) = seeks["payload"]["seeks"].as_array().and_then(|seeks| seeks.first()) {
+            seek = found.clone();
+            break;
+        }
+        time::delay_for(Duration::from_millis(100)).await;
+    }
+    assert_eq!(seek["player"], bot);
+
+    client.send(json!({"type": "acceptSeek", "payload": {"id": seek["id"]}})).await;
+    let started = client.receive_type("gameStarted").await;
+    let game_id = started["payload"]["gameId"].as_str().expect("game id").to_string();
+    (game_id, started["payload"]["white"] == "Guest1")
+}
+
+#[tokio::test]
+async fn the_practice_bot_seeks_and_plays() {
+    let mut config = memory_config();
+    config.bot = Some(BotConfig {
+        depth: 1,
+        think_time: 100,
+        ..Default::default()
+    });
+    let server = ExServer::with_config(0, config);
+    let (addr, running) = server.serve(([127, 0, 0, 1], 0).into());
+    tokio::spawn(running);
+    let mut client = TestClient::connect(addr).await;
+
+    let (game_id, is_white) = accept_bot_seek(&mut client, "PracticeBot").await;
+    if is_white {
+        client.send(json!({"type": "move", "payload": {"gameId": game_id, "ply": "a1"}})).await;
+        assert_eq!(client.receive_type("moved").await["payload"]["ply"], "a1");
+    }
+    let reply = client.receive_type("moved").await;
+    assert_eq!(reply["payload"]["gameId"], game_id.as_str());
+    assert!(reply["payload"]["ply"].as_str().expect("ply") != "a1");
+}
+
+//answers every search with the same move
+#[cfg(unix)]
+fn fake_engine(bestmove: &str) -> EngineConfig {
+    let script = format!(r#"
+while read -r line; do
+    case "$line" in
+        tei) echo "id name Fake"; echo teiok;;
+        isready) echo readyok;;
+        go*) echo "info depth 1"; echo "bestmove {}";;
+        quit) exit;;
+    esac
+done
+"#, bestmove);
+    EngineConfig {
+        command: String::from("sh"),
+        args: vec![String::from("-c"), script],
+    }
+}
+
+//starts a server whose bot is driven by the engine, plays a1 if the guest is white and returns the bot's reply
+#[cfg(unix)]
+async fn engine_reply(engine: EngineConfig) -> (TestClient, String, Value) {
+    let mut config = memory_config();
+    config.bot = Some(BotConfig {
+        name: String::from("FakeEngine"),
+        depth: 1,
+        think_time: 100,
+        engine: Some(engine),
+        ..Default::default()
+    });
+    let server = ExServer::with_config(0, config);
+    let (addr, running) = server.serve(([127, 0, 0, 1], 0).into());
+    tokio::spawn(running);
+    let mut client = TestClient::connect(addr).await;
+
+    let (game_id, is_white) = accept_bot_seek(&mut client, "FakeEngine").await;
+    if is_white {
+        client.send(json!({"type": "move", "payload": {"gameId": game_id, "ply": "a1"}})).await;
+        assert_eq!(client.receive_type("moved").await["payload"]["ply"], "a1");
+    }
+    let reply = client.receive_type("moved").await;
+    assert_eq!(reply["payload"]["gameId"], game_id.as_str());
+    (client, game_id, reply)
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn engines_play_over_tei() {
+    let (mut client, game_id, reply) = engine_reply(fake_engine("e5")).await;
+    assert_eq!(reply["payload"]["ply"], "e5");
+
+    client.send(json!({"type": "playerInfo", "payload": {"name": "FakeEngine"}})).await;
+    let info = client.receive_type("playerInfo").await;
+    assert_eq!(info["payload"]["isBot"], true);
+    assert_eq!(info["payload"]["currentGames"], json!([game_id]));
+}
+
+//the built in search takes over from an engine that answers nonsense, the game goes on either way
+#[cfg(unix)]
+#[tokio::test]
+async fn engines_that_answer_garbage_are_replaced() {
+    for garbage in ["é", "3a1+", "a1>99999999999999999999999999999"] {
+        let (_, _, reply) = engine_reply(fake_engine(garbage)).await;
+        let ply = reply["payload"]["ply"].as_str().expect("ply");
+        assert!(ply != "a1" && ply != garbage, "{}", garbage);
+    }
+}